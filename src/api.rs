@@ -1,5 +1,7 @@
 use crate::{
-	blocklist::{BlockList, FailedList, ListType, QueryInfo},
+	blocklist::{BlockList, FailedList, ListType, QueryInfo, UpdateDurations},
+	custom::{CustomEntry, CustomListType},
+	pause::{Pause, PauseState, Target},
 	CARGO_PKG_NAME, CARGO_PKG_VERSION
 };
 use log::info;
@@ -14,11 +16,12 @@ use poem_openapi::{
 use serde::Deserialize;
 use std::{
 	collections::HashMap,
+	net::IpAddr,
 	sync::{atomic::Ordering, Arc}
 };
 use time::OffsetDateTime;
 
-#[derive(Debug, Deserialize, Object)]
+#[derive(Clone, Debug, Deserialize, Object, PartialEq)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct Config {
 	port: u16,
@@ -72,15 +75,59 @@ struct Stats {
 	total_request: u64,
 	/// blocked dns request since start
 	blocked_request: u64,
+	/// dns request since start, which were not handled,
+	/// because their client was over its rate limit
+	rate_limited_request: u64,
+	/// dns request since start, which matched a qtype rule
+	qtype_rule_request: u64,
+	/// dns request since start, whose answer contained private addresses
+	/// removed by the rebind protection
+	rebind_request: u64,
 	blocklist_len: usize,
-	running_since: OffsetDateTime
+	running_since: OffsetDateTime,
+	/// running pauses of blocking
+	pause: PauseState,
+	/// durations of the phases of the last list update
+	last_update: Option<UpdateDurations>
 }
 
 struct Api {
 	doc_enable: bool,
 	stats: crate::Stats,
 	key: Option<String>,
-	blocklist: Arc<BlockList>
+	blocklist: Arc<BlockList>,
+	pause: Arc<Pause>
+}
+
+/// Return a response with the message of `err` and the status `status`.
+fn error(err: anyhow::Error, status: StatusCode) -> poem::Error {
+	poem::Error::from_string(format!("{err:#}"), status)
+}
+
+/// Return the clients selected by the parameters `client` and `group`.
+/// All clients are selected, if both are `None`.
+/// IPv4-mapped IPv6 addresses select the IPv4 client, like the handler sees it.
+fn pause_target(client: Option<String>, group: Option<String>) -> poem::Result<Target> {
+	let bad_request =
+		|msg: String| poem::Error::from_string(msg, StatusCode::BAD_REQUEST);
+	match (client, group) {
+		(None, None) => Ok(Target::All),
+		(Some(client), None) => client
+			.parse()
+			.map(|client: IpAddr| Target::Client(client.to_canonical()))
+			.map_err(|_| bad_request(format!("invalid client ip {client:?}"))),
+		(None, Some(group)) => Ok(Target::Group(group)),
+		(Some(_), Some(_)) => Err(bad_request(
+			"only one of client and group can be set".to_owned()
+		))
+	}
+}
+
+/// entries of the custom lists
+#[derive(Debug, Object)]
+struct CustomLists {
+	block: Vec<CustomEntry>,
+	allow: Vec<CustomEntry>
 }
 
 #[derive(Clone, Debug, poem_openapi::Object)]
@@ -88,8 +135,14 @@ pub(crate) struct OkList {
 	/// count of domains inside this List
 	pub(crate) len: u64,
 	pub(crate) url: String,
+	/// name of the list from the config
+	pub(crate) name: Option<String>,
 	#[oai(rename = "type")]
-	pub(crate) tipe: ListType
+	pub(crate) tipe: ListType,
+	/// time of the next download of the list
+	pub(crate) next_update: Option<OffsetDateTime>,
+	/// true if the list was modified since the last update
+	pub(crate) modified: bool
 }
 
 #[derive(Clone, Debug, poem_openapi::Object)]
@@ -99,10 +152,16 @@ pub(crate) struct UpdateFailedList {
 	/// count of domains inside this List
 	pub(crate) len: u64,
 	pub(crate) url: String,
+	/// name of the list from the config
+	pub(crate) name: Option<String>,
 	#[oai(rename = "type")]
 	pub(crate) tipe: ListType,
 	/// reason why updating list failed
-	pub(crate) error: String
+	pub(crate) error: String,
+	/// time of the next download of the list
+	pub(crate) next_update: Option<OffsetDateTime>,
+	/// true if the list was modified since the last update
+	pub(crate) modified: bool
 }
 
 #[derive(Clone, Debug, poem_openapi::Union)]
@@ -170,11 +229,118 @@ impl Api {
 		Ok(Json(Stats {
 			total_request: self.stats.total_request.load(Ordering::Relaxed),
 			blocked_request: self.stats.blocked_request.load(Ordering::Relaxed),
+			rate_limited_request: self.stats.rate_limited_request.load(Ordering::Relaxed),
+			qtype_rule_request: self.stats.qtype_rule_request.load(Ordering::Relaxed),
+			rebind_request: self.stats.rebind_request.load(Ordering::Relaxed),
 			blocklist_len: self.blocklist.len().await,
-			running_since: self.stats.running_since
+			running_since: self.stats.running_since,
+			pause: self.pause.state(),
+			last_update: self.blocklist.update_durations().await
 		}))
 	}
 
+	/// Return the entries of the custom block and allow list.
+	#[oai(path = "/custom.json", method = "get")]
+	async fn custom(&self, key: Key) -> poem::Result<Json<CustomLists>> {
+		key.validate(self)?;
+		Ok(Json(CustomLists {
+			block: self.blocklist.custom(CustomListType::Block).await,
+			allow: self.blocklist.custom(CustomListType::Allow).await
+		}))
+	}
+
+	/// add the `domain` (`<domain>`, `*.<domain>` or `||<domain>^`) to the custom list `list`.
+	/// The entry is applied at once and is kept after restarting.
+	/// An existing entry of the same domain is replaced.
+	#[oai(path = "/custom.json", method = "post")]
+	async fn add_custom(
+		&self,
+		key: Key,
+		list: Query<CustomListType>,
+		domain: Query<String>,
+		comment: Query<Option<String>>
+	) -> poem::Result<Json<Vec<CustomEntry>>> {
+		key.validate(self)?;
+		let entry = CustomEntry::new(&domain, comment.as_deref())
+			.map_err(|err| error(err, StatusCode::BAD_REQUEST))?;
+		info!("add {:?} to the custom {:?} list", entry.domain, list.0);
+		self.blocklist
+			.add_custom(list.0, entry)
+			.await
+			.map_err(|err| error(err, StatusCode::INTERNAL_SERVER_ERROR))?;
+		Ok(Json(self.blocklist.custom(list.0).await))
+	}
+
+	/// remove the entry `domain` from the custom list `list`.
+	#[oai(path = "/custom.json", method = "delete")]
+	async fn remove_custom(
+		&self,
+		key: Key,
+		list: Query<CustomListType>,
+		domain: Query<String>
+	) -> poem::Result<Json<Vec<CustomEntry>>> {
+		key.validate(self)?;
+		let entry = CustomEntry::new(&domain, None)
+			.map_err(|err| error(err, StatusCode::BAD_REQUEST))?;
+		info!(
+			"remove {:?} from the custom {:?} list",
+			entry.domain, list.0
+		);
+		let removed = self
+			.blocklist
+			.remove_custom(list.0, &entry.domain)
+			.await
+			.map_err(|err| error(err, StatusCode::INTERNAL_SERVER_ERROR))?;
+		if !removed {
+			return Err(poem::Error::from_string(
+				format!(
+					"custom {:?} list does not contain {:?}",
+					list.0, entry.domain
+				),
+				StatusCode::NOT_FOUND
+			));
+		}
+		Ok(Json(self.blocklist.custom(list.0).await))
+	}
+
+	/// pause blocking for `minutes`,
+	/// for all clients or only for the client ip `client` or the group `group`.
+	/// Blocking, qtype rules and safe search are paused,
+	/// local records, hosts files and the rebind protection are still used.
+	/// A running pause of the same clients is replaced.
+	#[oai(path = "/pause.json", method = "post")]
+	async fn pause(
+		&self,
+		key: Key,
+		minutes: Query<u32>,
+		client: Query<Option<String>>,
+		group: Query<Option<String>>
+	) -> poem::Result<Json<PauseState>> {
+		key.validate(self)?;
+		let target = pause_target(client.0, group.0)?;
+		info!("pause blocking of {target:?} for {} minutes", minutes.0);
+		self.pause
+			.pause(target, time::Duration::minutes(minutes.0.into()))
+			.map_err(|err| error(err, StatusCode::BAD_REQUEST))?;
+		Ok(Json(self.pause.state()))
+	}
+
+	/// resume blocking before the end of the pause,
+	/// for all clients or only for the client ip `client` or the group `group`.
+	#[oai(path = "/resume.json", method = "post")]
+	async fn resume(
+		&self,
+		key: Key,
+		client: Query<Option<String>>,
+		group: Query<Option<String>>
+	) -> poem::Result<Json<PauseState>> {
+		key.validate(self)?;
+		let target = pause_target(client.0, group.0)?;
+		info!("resume blocking of {target:?}");
+		self.pause.resume(&target);
+		Ok(Json(self.pause.state()))
+	}
+
 	/// landing page
 	#[oai(path = "/", method = "get", hidden = true)]
 	async fn index(&self) -> Html<String> {
@@ -193,12 +359,14 @@ impl Api {
 pub(crate) async fn init(
 	config: Option<Config>,
 	stats: crate::Stats,
-	blocklist: Arc<BlockList>
+	blocklist: Arc<BlockList>,
+	pause: Arc<Pause>
 ) -> anyhow::Result<()> {
 	if let Some(config) = config {
 		let address = format!("{}:{}", config.listen, config.port);
 		let api_data = Api {
 			blocklist,
+			pause,
 			doc_enable: config.show_doc,
 			stats,
 			key: config.admin_key
@@ -1,36 +1,85 @@
-use std::collections::HashMap;
+use std::{
+	collections::HashMap,
+	hash::{DefaultHasher, Hash, Hasher},
+	net::IpAddr,
+	panic,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant}
+};
 
-use crate::{api, get_file, parser, trie::Trie, LIST_DIR};
+use crate::{
+	api,
+	custom::{CustomEntry, CustomListType, CustomLists},
+	get_file,
+	ip_trie::IpTrie,
+	parser,
+	regex_set::DomainRegexSet,
+	schedule::{self, Schedule},
+	trie::{ListFilter, Trie},
+	BlockConfig, GroupConfig, ListConfig, LIST_DIR
+};
 use anyhow::Context;
+use bit_vec::BitVec;
 use log::{error, info, warn};
 use num_format::{Locale, ToFormattedString};
-use tokio::{fs::create_dir_all, sync::RwLock};
+use time::OffsetDateTime;
+use tokio::{
+	fs::create_dir_all,
+	spawn,
+	sync::{Mutex as AsyncMutex, MutexGuard, OnceCell, RwLock, Semaphore},
+	task::{spawn_blocking, JoinHandle}
+};
 use url::Url;
 
+/// label of the custom block list, which is managed by the api
+const CUSTOM: &str = "custom";
+
+/// delay before a failed download is retried, which is doubled after each failure
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
 #[derive(Clone, Debug, poem_openapi::Object)]
 pub(crate) struct ListInfo {
 	/// count of domains inside this List
 	pub(crate) len: u64,
 	pub(crate) url: String,
+	/// name of the list from the config
+	pub(crate) name: Option<String>,
+	#[oai(rename = "type")]
+	pub(crate) tipe: ListType,
 	/// If `Some` the list has partly fail (for example downloading a newer version)
 	/// String stores error messages.
-	pub(crate) error: Option<String>
+	pub(crate) error: Option<String>,
+	/// true if the list was modified since the last update
+	pub(crate) modified: bool
 }
 
-#[derive(Clone, Debug, poem_openapi::Enum)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, poem_openapi::Enum)]
 #[oai(rename_all = "lowercase")]
 pub(crate) enum ListType {
 	Block,
-	Allow
+	Allow,
+	/// hosts file, whose entries are served as local answers or are blocked
+	Hosts,
+	/// list of blocked ip ranges
+	Ip,
+	/// list of regular expressions, which block the matched domains
+	Regex,
+	/// list of regular expressions, which allow the matched domains
+	#[oai(rename = "regex_allow")]
+	RegexAllow
 }
 
 #[derive(Clone, Debug, poem_openapi::Object)]
 pub(crate) struct FailedList {
 	pub(crate) url: String,
+	/// name of the list from the config
+	pub(crate) name: Option<String>,
 	#[oai(rename = "type")]
 	pub(crate) tipe: ListType,
 	/// reason why loading list failed
-	pub(crate) error: String
+	pub(crate) error: String,
+	/// time of the next download of the list
+	pub(crate) next_update: Option<OffsetDateTime>
 }
 
 #[derive(Debug, Default)]
@@ -43,18 +92,311 @@ pub(crate) struct InnerBlockList {
 	/// store list, wich could not be loadedi
 	failed_lists: Vec<FailedList>,
 	/// info about allow list
-	allow_list_info: Vec<ListInfo>
+	allow_list_info: Vec<ListInfo>,
+	/// blocked ip ranges
+	ip_trie: IpTrie,
+	/// info about ip block lists
+	ip_list_info: Vec<ListInfo>,
+	/// addresses of domains from hosts files, together with the index of their list
+	hosts: HashMap<String, Vec<(IpAddr, usize)>>,
+	/// lists selected by the client groups, in the same order as the groups of the config
+	groups: Vec<GroupLists>,
+	/// block lists, whose entries block their subdomains too
+	subdomain_lists: BitVec,
+	/// schedules of the block lists, together with the index of their list
+	schedules: Vec<(usize, Schedule)>,
+	/// expressions of the regex lists, which are checked after `trie`
+	regex: DomainRegexSet,
+	/// expressions of the regex allow lists
+	allow_regex: DomainRegexSet,
+	/// entries of the custom block list, which are checked before all other lists
+	custom_block: Trie,
+	/// entries of the custom allow list, which are checked before `custom_block`
+	custom_allow: Trie,
+	/// durations of the phases of the last update
+	update_durations: Option<UpdateDurations>
 }
 
 impl InnerBlockList {
 	pub(crate) fn allow(&mut self, domain: &str, allow_subdomains: bool) {
-		self.trie.allow(domain, allow_subdomains);
+		let list_info_index = self.allow_list_info.len();
+		self.trie.allow(domain, allow_subdomains, list_info_index);
+	}
+
+	/// Return the lists selected by the client group `group`.
+	/// All lists are selected, if `group` is `None`.
+	/// `active` replaces the selected block lists, see [`Self::active_lists`].
+	fn filter<'a>(
+		&'a self,
+		group: Option<usize>,
+		active: Option<&'a BitVec>
+	) -> ListFilter<'a> {
+		let group = group.and_then(|group| self.groups.get(group));
+		ListFilter {
+			block: active.or_else(|| group.and_then(|group| group.block.as_ref())),
+			allow: group.and_then(|group| group.allow.as_ref()),
+			subdomains: Some(&self.subdomain_lists)
+		}
+	}
+
+	/// Return the block lists selected by the client group `group`,
+	/// without the lists whose schedule is inactive right now.
+	/// Return `None` if all scheduled lists are active.
+	fn active_lists(&self, group: Option<usize>) -> Option<BitVec> {
+		if self.schedules.is_empty() {
+			return None;
+		}
+		let now = schedule::now();
+		let mut inactive = self
+			.schedules
+			.iter()
+			.filter(|(_, schedule)| !schedule.active_at(now))
+			.peekable();
+		inactive.peek()?;
+		let mut active = self
+			.filter(group, None)
+			.block
+			.cloned()
+			.unwrap_or_else(|| BitVec::from_elem(self.block_list_info.len(), true));
+		for (i, _) in inactive {
+			active.set(*i, false);
+		}
+		Some(active)
+	}
+}
+
+/// Lists selected by a client group.
+/// `true`s are the indices of the selected lists, `None` selects all lists.
+#[derive(Debug, Default)]
+struct GroupLists {
+	block: Option<BitVec>,
+	allow: Option<BitVec>,
+	ip: Option<BitVec>
+}
+
+/// Return the lists of `list_info`, whose url is contained by `urls`.
+fn select(urls: Option<&Vec<Url>>, list_info: &[ListInfo]) -> Option<BitVec> {
+	urls.map(|urls| {
+		list_info
+			.iter()
+			.map(|info| urls.iter().any(|url| url.as_str() == info.url))
+			.collect()
+	})
+}
+
+/// Durations of the phases of a list update in milliseconds.
+#[derive(Clone, Debug, poem_openapi::Object)]
+pub(crate) struct UpdateDurations {
+	/// downloading (or restoring from cache) all lists
+	download: u64,
+	/// parsing all lists
+	parse: u64,
+	/// inserting the entries of the parsed lists
+	build: u64
+}
+
+/// A list, which was downloaded (or restored from cache), but is not parsed yet.
+struct RawList {
+	url: Url,
+	tipe: ListType,
+	/// refresh interval, which overrides the one of the config
+	interval: Option<Duration>,
+	/// true if the cached version was used, because the refresh was not due yet
+	restored: bool,
+	raw: Option<String>,
+	/// errors, which were recovered from (for example by using a cached version)
+	errors: String,
+	/// true if the content differs from the last loaded version of the list
+	modified: bool
+}
+
+/// A parsed list, see [`RawList`].
+struct LoadedList<T> {
+	url: Url,
+	tipe: ListType,
+	interval: Option<Duration>,
+	restored: bool,
+	/// the parsed list together with the errors, which were recovered from,
+	/// and whether the list was modified, or the reason why the list could not be loaded
+	list: Result<(T, String, bool), FailedList>
+}
+
+impl RawList {
+	fn parse<T>(self, parse: fn(&str, &str) -> Result<T, String>) -> LoadedList<T> {
+		let Self {
+			url,
+			tipe,
+			interval,
+			restored,
+			raw,
+			mut errors,
+			modified
+		} = self;
+		let list = match raw {
+			None => {
+				error!("skipp list {url}");
+				Err(errors)
+			},
+			Some(raw) => match parse(url.as_str(), &raw) {
+				Ok(list) => Ok((list, errors, modified)),
+				Err(msg) => {
+					error!("parsing Blockist {}\n{msg}", url.as_str());
+					errors += &msg;
+					Err(errors)
+				}
+			}
+		};
+		let list = list.map_err(|error| FailedList {
+			url: url.as_str().to_owned(),
+			name: None,
+			tipe: tipe.clone(),
+			error,
+			next_update: None
+		});
+		LoadedList {
+			url,
+			tipe,
+			interval,
+			restored,
+			list
+		}
+	}
+}
+
+/// Parse `raw_lists` on blocking threads, so the lists are parsed in parallel.
+fn spawn_parse<T: Send + 'static>(
+	raw_lists: impl IntoIterator<Item = RawList>,
+	parse: fn(&str, &str) -> Result<T, String>
+) -> Vec<JoinHandle<LoadedList<T>>> {
+	raw_lists
+		.into_iter()
+		.map(|list| spawn_blocking(move || list.parse(parse)))
+		.collect()
+}
+
+/// Return the hash of the content of a list, to detect modified lists.
+fn content_hash(raw: &str) -> u64 {
+	let mut hasher = DefaultHasher::new();
+	raw.hash(&mut hasher);
+	hasher.finish()
+}
+
+/// Return the enabled block lists of `config`.
+fn enabled_lists(config: &BlockConfig) -> Vec<&ListConfig> {
+	config
+		.lists
+		.iter()
+		.filter(|list| {
+			if !list.enabled {
+				info!("skip disabled list {}", list.url);
+			}
+			list.enabled
+		})
+		.collect()
+}
+
+/// Return the urls of the enabled `block_lists` and of the other lists of `config`,
+/// together with their type and their own refresh interval,
+/// in the order in which they are parsed.
+fn list_urls<'a>(
+	config: &'a BlockConfig,
+	block_lists: &'a [&'a ListConfig]
+) -> impl Iterator<Item = (&'a Url, ListType, Option<Duration>)> + 'a {
+	block_lists
+		.iter()
+		.map(|list| (&list.url, ListType::Block, list.interval))
+		.chain(
+			[
+				(&config.hosts, ListType::Hosts),
+				(&config.regex_lists, ListType::Regex),
+				(&config.ip_blocklist, ListType::Ip),
+				(&config.allow_list, ListType::Allow),
+				(&config.regex_allow_list, ListType::RegexAllow)
+			]
+			.into_iter()
+			.flat_map(|(urls, tipe)| {
+				urls.iter().map(move |url| (url, tipe.clone(), None))
+			})
+		)
+}
+
+/// Wait for all `tasks` and return their output in the same order.
+async fn join_all<T>(tasks: Vec<JoinHandle<T>>) -> Vec<T> {
+	let mut outputs = Vec::with_capacity(tasks.len());
+	for task in tasks {
+		outputs.push(
+			task.await
+				.unwrap_or_else(|err| panic::resume_unwind(err.into_panic()))
+		);
+	}
+	outputs
+}
+
+/// Compile the expressions of the regex lists.
+/// If the lists can not be compiled together (for example, because of the size limit),
+/// they are added one after another and lists, which can not be added, are marked as failed.
+fn compile_regex(
+	mut entries: Vec<(String, usize)>,
+	list_info: &mut [ListInfo]
+) -> DomainRegexSet {
+	let err = match DomainRegexSet::new(&entries) {
+		Ok(set) => return set,
+		Err(err) => err
+	};
+	error!("failed to compile regex lists: {err}");
+	let mut set = DomainRegexSet::default();
+	let mut compiled = 0;
+	while compiled < entries.len() {
+		let index = entries[compiled].1;
+		let end = compiled
+			+ entries[compiled ..]
+				.iter()
+				.take_while(|(_, i)| *i == index)
+				.count();
+		match DomainRegexSet::new(&entries[.. end]) {
+			Ok(new_set) => {
+				set = new_set;
+				compiled = end;
+			},
+			Err(err) => {
+				let list_info = &mut list_info[index];
+				error!("skip regex list {}: {err}", list_info.label());
+				list_info.len = 0;
+				list_info.error = Some(format!("failed to compile list: {err}"));
+				entries.drain(compiled .. end);
+			}
+		}
+	}
+	set
+}
+
+impl ListInfo {
+	/// Return the name of the list, or its url if it has no name.
+	fn label(&self) -> &str {
+		self.name.as_deref().unwrap_or(&self.url)
 	}
 }
 
 #[derive(Debug, Default)]
 pub(crate) struct BlockList {
-	rw_lock: RwLock<InnerBlockList>
+	rw_lock: RwLock<InnerBlockList>,
+	/// refresh schedule of the downloaded lists, by their url
+	refresh: Mutex<HashMap<String, Refresh>>,
+	/// hash of the last loaded content of the lists,
+	/// by their type and their position among the lists of this type,
+	/// so a url used by multiple lists is compared against its own last content
+	hashes: Mutex<HashMap<(ListType, usize), u64>>,
+	/// custom lists managed by the api, loaded on first use
+	custom: OnceCell<AsyncMutex<CustomLists>>
+}
+
+/// Refresh schedule of a list.
+#[derive(Debug)]
+struct Refresh {
+	/// time at which the list should be downloaded again
+	next: Instant,
+	/// count of failed downloads in a row
+	failures: u32
 }
 
 impl BlockList {
@@ -62,15 +404,262 @@ impl BlockList {
 		BlockList::default()
 	}
 
-	///Clear and update the current Blocklist, to all entries of the list at from `adlist`.
+	/// Return the custom lists.
+	/// To keep both consistent, the lock must be acquired before `rw_lock`.
+	async fn custom_lists(&self) -> MutexGuard<'_, CustomLists> {
+		self.custom
+			.get_or_init(|| async { AsyncMutex::new(CustomLists::load().await) })
+			.await
+			.lock()
+			.await
+	}
+
+	/// Return true if the cached version of the list `url` should be used,
+	/// because its next refresh is not due yet.
+	fn use_cache(&self, url: &Url, restore_from_cache: bool) -> bool {
+		restore_from_cache
+			|| self
+				.refresh
+				.lock()
+				.unwrap()
+				.get(url.as_str())
+				.is_some_and(|refresh| Instant::now() < refresh.next)
+	}
+
+	/// Schedule the next download of the list `url`, which was downloaded right now.
+	/// Failed downloads are retried with exponential backoff, up to `interval`.
+	/// Otherwise a random delay of up to `jitter` is added to `interval`.
+	fn schedule(&self, url: &Url, interval: Duration, jitter: Duration, failed: bool) {
+		let mut refresh = self.refresh.lock().unwrap();
+		let failures = refresh
+			.get(url.as_str())
+			.map_or(0, |refresh| refresh.failures);
+		let (delay, failures) = if failed {
+			let backoff = RETRY_INTERVAL.saturating_mul(2_u32.saturating_pow(failures));
+			(backoff.min(interval), failures.saturating_add(1))
+		} else {
+			(interval + jitter.mul_f64(rand::random()), 0)
+		};
+		if failed {
+			warn!("retry to download {url} in {delay:?}");
+		}
+		refresh.insert(url.as_str().to_owned(), Refresh {
+			next: Instant::now() + delay,
+			failures
+		});
+	}
+
+	/// Return the duration until the next of the lists `urls` should be downloaded again.
+	/// `default` is returned if there are no lists.
+	fn next_update<'a>(
+		&self,
+		urls: impl IntoIterator<Item = &'a Url>,
+		default: Duration
+	) -> Duration {
+		let refresh = self.refresh.lock().unwrap();
+		let now = Instant::now();
+		urls.into_iter()
+			.map(|url| {
+				refresh
+					.get(url.as_str())
+					.map(|refresh| refresh.next.saturating_duration_since(now))
+					.unwrap_or_default()
+			})
+			.min()
+			.unwrap_or(default)
+	}
+
+	/// Return the time of the next download of the list `url`.
+	fn next_refresh(&self, url: &str) -> Option<OffsetDateTime> {
+		let refresh = self.refresh.lock().unwrap();
+		let next = refresh
+			.get(url)?
+			.next
+			.saturating_duration_since(Instant::now());
+		Some(OffsetDateTime::now_utc() + next)
+	}
+
+	/// Download the lists concurrently, with at most `parallel_downloads` at the same time.
+	/// Lists, whose refresh is not due yet, are restored from cache instead.
+	/// The lists are returned in the same order.
+	async fn download(
+		&self,
+		lists: Vec<(Url, ListType, Option<Duration>)>,
+		config: &BlockConfig,
+		restore_from_cache: bool
+	) -> Vec<RawList> {
+		let permits = Arc::new(Semaphore::new(config.parallel_downloads()));
+		let tasks = lists
+			.into_iter()
+			.map(|(url, tipe, interval)| {
+				let restored = self.use_cache(&url, restore_from_cache);
+				let permits = Arc::clone(&permits);
+				spawn(async move {
+					let _permit = permits.acquire_owned().await;
+					let (raw, errors, downloaded) = get_file(&url, restored, true).await;
+					let hash = raw.as_deref().map(content_hash);
+					let list = RawList {
+						url,
+						tipe,
+						interval,
+						restored,
+						raw,
+						errors,
+						modified: downloaded
+					};
+					(list, hash)
+				})
+			})
+			.collect();
+		let lists = join_all(tasks).await;
+		let mut hashes = self.hashes.lock().unwrap();
+		let mut positions: HashMap<ListType, usize> = HashMap::new();
+		lists
+			.into_iter()
+			.map(|(mut list, hash)| {
+				let position = positions.entry(list.tipe.clone()).or_default();
+				let key = (list.tipe.clone(), *position);
+				*position += 1;
+				if let Some(hash) = hash {
+					// lists loaded for the first time are modified, if they were downloaded
+					if let Some(old) = hashes.insert(key, hash) {
+						list.modified = old != hash;
+					}
+				}
+				list
+			})
+			.collect()
+	}
+
+	/// Schedule the next download of `list`, if it was downloaded.
+	/// Return the parsed list, see [`LoadedList::list`].
+	fn loaded<T>(
+		&self,
+		list: LoadedList<T>,
+		config: &BlockConfig
+	) -> Result<(T, String, bool), FailedList> {
+		if !list.restored {
+			// errors of downloaded lists are caused by a failed download or an invalid list
+			let failed = list
+				.list
+				.as_ref()
+				.map_or(true, |(_, errors, _)| !errors.is_empty());
+			self.schedule(
+				&list.url,
+				list.interval.unwrap_or(config.update_interval()),
+				config.update_jitter(),
+				failed
+			);
+		}
+		list.list
+	}
+
+	/// Add the info of the regex lists `lists` to `list_info`.
+	/// Return the expressions together with the index of their list inside `list_info`.
+	fn add_regex_lists(
+		&self,
+		lists: Vec<LoadedList<parser::RegexList>>,
+		config: &BlockConfig,
+		list_info: &mut Vec<ListInfo>,
+		failed_lists: &mut Vec<FailedList>
+	) -> Vec<(String, usize)> {
+		let mut entries = Vec::new();
+		for list in lists {
+			let url = list.url.clone();
+			let tipe = list.tipe.clone();
+			match self.loaded(list, config) {
+				Err(failed_list) => failed_lists.push(failed_list),
+				Ok((list, list_errors, modified)) => {
+					let list_info_index = list_info.len();
+					list_info.push(ListInfo {
+						len: list.entries.len() as u64,
+						url: url.as_str().to_owned(),
+						name: None,
+						tipe,
+						error: (!list_errors.is_empty()).then_some(list_errors),
+						modified
+					});
+					entries.extend(
+						list.entries
+							.into_iter()
+							.map(|regex| (regex, list_info_index))
+					);
+				}
+			}
+		}
+		entries
+	}
+
+	///Clear and update the current Blocklist, to all entries of the lists from `config`.
 	///if `use_cache` is set true, cached list, will not be redownloaded (faster init)
-	// TODO: clean this up
+	///Lists, whose next refresh is not due yet, are restored from cache too.
+	///If none of the downloaded lists was modified, the current Blocklist is kept.
+	///`groups` selects subsets of the lists for their clients.
+	///Return the duration until the next list should be downloaded again.
 	pub(crate) async fn update(
 		&self,
-		adlist: &Vec<Url>,
-		allow_list: &Vec<Url>,
+		config: &BlockConfig,
+		groups: &[GroupConfig],
 		restore_from_cache: bool
-	) {
+	) -> Duration {
+		let block_lists = enabled_lists(config);
+		let (raw_lists, download) = self
+			.download_lists(config, &block_lists, restore_from_cache)
+			.await;
+		if !restore_from_cache && self.keep(config, &raw_lists).await {
+			info!("👮✅ lists were not modified, keep blocklist");
+			return self.next_update(
+				list_urls(config, &block_lists).map(|(url, ..)| url),
+				config.update_interval()
+			);
+		}
+		let (inner_block_list, next_update) = self
+			.build_from(config, groups, &block_lists, raw_lists, download)
+			.await;
+		self.replace(inner_block_list).await;
+		next_update
+	}
+
+	/// Return true if none of the `raw_lists` was modified or failed,
+	/// so the current Blocklist can be kept instead of building it again.
+	/// The next download of the kept lists is scheduled.
+	async fn keep(&self, config: &BlockConfig, raw_lists: &[RawList]) -> bool {
+		let mut guard = self.rw_lock.write().await;
+		if guard.update_durations.is_none()
+			|| raw_lists.iter().any(|list| {
+				list.modified || list.raw.is_none() || !list.errors.is_empty()
+			}) {
+			return false;
+		}
+		for list in raw_lists.iter().filter(|list| !list.restored) {
+			self.schedule(
+				&list.url,
+				list.interval.unwrap_or(config.update_interval()),
+				config.update_jitter(),
+				false
+			);
+		}
+		let guard = &mut *guard;
+		for list_info in guard
+			.block_list_info
+			.iter_mut()
+			.chain(&mut guard.allow_list_info)
+			.chain(&mut guard.ip_list_info)
+		{
+			list_info.modified = false;
+		}
+		true
+	}
+
+	/// Download the lists of `config` with the enabled `block_lists`,
+	/// see [`Self::download`].
+	/// Return the lists together with the duration of the download.
+	async fn download_lists(
+		&self,
+		config: &BlockConfig,
+		block_lists: &[&ListConfig],
+		restore_from_cache: bool
+	) -> (Vec<RawList>, Duration) {
 		if restore_from_cache {
 			info!("👮💾 restore blocklist, from cache");
 		} else {
@@ -82,186 +671,858 @@ impl BlockList {
 		{
 			error!("{err:?}");
 		}
-		let mut trie = Trie::new();
-		let mut block_list_info = Vec::new();
-		let mut failed_lists = Vec::new();
-
-		// block list
-		for url in adlist {
-			let (raw_list, mut list_errors) =
-				get_file(url, restore_from_cache, true).await;
-			match raw_list {
-				None => {
-					error!("skipp list {url}");
-					failed_lists.push(FailedList {
-						url: url.as_str().to_owned(),
-						error: list_errors,
-						tipe: ListType::Block
-					})
-				},
-				Some(raw_list) => {
-					let result = parser::Blocklist::parse(url.as_str(), &raw_list);
-					match result {
-						Err(err) => {
-							let msg = err.msg();
-							error!("parsing Blockist {}\n{msg}", url.as_str());
-							list_errors += &msg;
-							failed_lists.push(FailedList {
-								url: url.as_str().to_owned(),
-								error: list_errors,
-								tipe: ListType::Block
-							})
-						},
-						Ok(list) => {
-							let mut count = 0;
-							for entry in list.entries {
-								if !trie.insert(&entry.domain().0, block_list_info.len())
-								{
-									// domain was not already add by this list
+		let started = Instant::now();
+		let lists = list_urls(config, block_lists)
+			.map(|(url, tipe, interval)| (url.clone(), tipe, interval))
+			.collect();
+		let raw_lists = self.download(lists, config, restore_from_cache).await;
+		let download = started.elapsed();
+		info!("downloaded lists in {download:?}");
+		(raw_lists, download)
+	}
+
+	/// Load the lists like [`Self::update`], without replacing the current Blocklist.
+	/// Return the new Blocklist, which can be used by [`Self::replace`],
+	/// and the duration until the next list should be downloaded again.
+	pub(crate) async fn build(
+		&self,
+		config: &BlockConfig,
+		groups: &[GroupConfig],
+		restore_from_cache: bool
+	) -> (InnerBlockList, Duration) {
+		let block_lists = enabled_lists(config);
+		let (raw_lists, download) = self
+			.download_lists(config, &block_lists, restore_from_cache)
+			.await;
+		self.build_from(config, groups, &block_lists, raw_lists, download)
+			.await
+	}
+
+	/// Build the Blocklist of `config` from the downloaded `raw_lists`, see [`Self::build`].
+	async fn build_from(
+		&self,
+		config: &BlockConfig,
+		groups: &[GroupConfig],
+		block_lists: &[&ListConfig],
+		raw_lists: Vec<RawList>,
+		download: Duration
+	) -> (InnerBlockList, Duration) {
+		// the lists are grouped by their type, each group keeps the order of the config
+		let started = Instant::now();
+		let mut domain_lists = Vec::new();
+		let mut regex_lists = Vec::new();
+		let mut ip_lists = Vec::new();
+		let mut allow_lists = Vec::new();
+		let mut regex_allow_lists = Vec::new();
+		for list in raw_lists {
+			match list.tipe {
+				// block lists are followed by the hosts files
+				ListType::Block | ListType::Hosts => domain_lists.push(list),
+				ListType::Regex => regex_lists.push(list),
+				ListType::Ip => ip_lists.push(list),
+				ListType::Allow => allow_lists.push(list),
+				ListType::RegexAllow => regex_allow_lists.push(list)
+			}
+		}
+		let domain_lists = spawn_parse(domain_lists, |path, raw| {
+			parser::Blocklist::parse(path, raw).map_err(|err| err.msg())
+		});
+		let regex_lists = spawn_parse(regex_lists, |path, raw| {
+			parser::RegexList::parse(path, raw).map_err(|err| err.msg())
+		});
+		let ip_lists = spawn_parse(ip_lists, |path, raw| {
+			parser::IpBlocklist::parse(path, raw).map_err(|err| err.msg())
+		});
+		let allow_lists = spawn_parse(allow_lists, |path, raw| {
+			parser::Blocklist::parse(path, raw).map_err(|err| err.msg())
+		});
+		let regex_allow_lists = spawn_parse(regex_allow_lists, |path, raw| {
+			parser::RegexList::parse(path, raw).map_err(|err| err.msg())
+		});
+		let domain_lists = join_all(domain_lists).await;
+		let regex_lists = join_all(regex_lists).await;
+		let ip_lists = join_all(ip_lists).await;
+		let allow_lists = join_all(allow_lists).await;
+		let regex_allow_lists = join_all(regex_allow_lists).await;
+		let parse = started.elapsed();
+		info!("parsed lists in {parse:?}");
+
+		// entries are inserted one list after the other, to keep the indices of the lists stable
+		let started = Instant::now();
+		let mut inner_block_list = InnerBlockList {
+			trie: Trie::new(),
+			ip_trie: IpTrie::new(),
+			..Default::default()
+		};
+		let lists = block_lists
+			.iter()
+			.map(|list| {
+				(
+					list.name.as_ref(),
+					list.include_subdomains,
+					list.schedule.as_ref()
+				)
+			})
+			.chain(config.hosts.iter().map(|_| (None, None, None)));
+		for ((name, include_subdomains, schedule), list) in lists.zip(domain_lists) {
+			let url = list.url.clone();
+			let tipe = list.tipe.clone();
+			let list = self.loaded(list, config);
+			match list {
+				Err(failed_list) => inner_block_list.failed_lists.push(FailedList {
+					name: name.cloned(),
+					..failed_list
+				}),
+				Ok((list, list_errors, modified)) => {
+					let list_info_index = inner_block_list.block_list_info.len();
+					inner_block_list
+						.subdomain_lists
+						.push(include_subdomains.unwrap_or(config.include_subdomains));
+					if let Some(schedule) = schedule {
+						inner_block_list
+							.schedules
+							.push((list_info_index, schedule.clone()));
+					}
+					let mut count = 0;
+					for entry in list.entries {
+						// hosts files map domains to local addresses,
+						// only unspecified and loopback addresses block the domain
+						if let (
+							ListType::Hosts,
+							parser::Line::IpDomain(ip, domain)
+							| parser::Line::IpIfaceDomain(ip, _, domain)
+						) = (&tipe, &entry)
+						{
+							if !ip.is_unspecified() && !ip.is_loopback() {
+								let ips = inner_block_list
+									.hosts
+									.entry(domain.0.to_lowercase())
+									.or_default();
+								if !ips.contains(&(*ip, list_info_index)) {
+									ips.push((*ip, list_info_index));
 									count += 1;
 								}
+								continue;
 							}
-							block_list_info.push(ListInfo {
-								len: count,
-								url: url.as_str().to_owned(),
-								error: (!list_errors.is_empty()).then_some(list_errors)
-							});
+						}
+						if !inner_block_list.trie.insert(
+							&entry.domain().0,
+							list_info_index,
+							entry.include_subdomains()
+						) {
+							// domain was not already add by this list
+							count += 1;
 						}
 					}
+					inner_block_list.block_list_info.push(ListInfo {
+						len: count,
+						url: url.as_str().to_owned(),
+						name: name.cloned(),
+						tipe,
+						error: (!list_errors.is_empty()).then_some(list_errors),
+						modified
+					});
 				}
 			}
 		}
 
-		let mut inner_block_list = InnerBlockList {
-			trie,
-			block_list_info,
-			failed_lists,
-			allow_list_info: Vec::new()
-		};
+		// regex block list
+		let regex = self.add_regex_lists(
+			regex_lists,
+			config,
+			&mut inner_block_list.block_list_info,
+			&mut inner_block_list.failed_lists
+		);
+		inner_block_list.regex =
+			compile_regex(regex, &mut inner_block_list.block_list_info);
+
+		// ip block list
+		for list in ip_lists {
+			let url = list.url.clone();
+			let list = self.loaded(list, config);
+			match list {
+				Err(failed_list) => inner_block_list.failed_lists.push(failed_list),
+				Ok((list, list_errors, modified)) => {
+					let mut count = 0;
+					for net in list.entries {
+						if !inner_block_list
+							.ip_trie
+							.insert(net, inner_block_list.ip_list_info.len())
+						{
+							count += 1;
+						}
+					}
+					inner_block_list.ip_list_info.push(ListInfo {
+						len: count,
+						url: url.as_str().to_owned(),
+						name: None,
+						tipe: ListType::Ip,
+						error: (!list_errors.is_empty()).then_some(list_errors),
+						modified
+					});
+				}
+			}
+		}
 
 		// allow list
-		for url in allow_list {
+		for list in allow_lists {
 			info!("load allow list");
-			let (raw_list, mut list_errors) =
-				get_file(url, restore_from_cache, true).await;
-			match raw_list {
-				None => error!("skipp list {url}"),
-				Some(raw_list) => {
-					let result = parser::Blocklist::parse(url.as_str(), &raw_list);
-					match result {
-						Err(err) => {
-							let msg = err.msg();
-							error!("parsing Blockist {}\n{msg}", url.as_str());
-							list_errors += &msg;
-							inner_block_list.failed_lists.push(FailedList {
-								url: url.as_str().to_owned(),
-								error: list_errors,
-								tipe: ListType::Allow
-							})
-						},
-						Ok(list) => {
-							for entry in list.entries {
-								if entry.domain().0.starts_with("*.") {
-									inner_block_list.allow(&entry.domain().0[2 ..], true);
-								} else {
-									inner_block_list.allow(&entry.domain().0, false);
-								}
-							}
-						},
+			let url = list.url.clone();
+			let list = self.loaded(list, config);
+			match list {
+				Err(failed_list) => inner_block_list.failed_lists.push(failed_list),
+				Ok((list, list_errors, modified)) => {
+					let len = list.entries.len() as u64;
+					for entry in list.entries {
+						inner_block_list
+							.allow(&entry.domain().0, entry.include_subdomains());
 					}
+					inner_block_list.allow_list_info.push(ListInfo {
+						len,
+						url: url.as_str().to_owned(),
+						name: None,
+						tipe: ListType::Allow,
+						error: (!list_errors.is_empty()).then_some(list_errors),
+						modified
+					});
 				}
 			}
 		}
+		// regex allow list
+		let allow_regex = self.add_regex_lists(
+			regex_allow_lists,
+			config,
+			&mut inner_block_list.allow_list_info,
+			&mut inner_block_list.failed_lists
+		);
+		inner_block_list.allow_regex =
+			compile_regex(allow_regex, &mut inner_block_list.allow_list_info);
+
 		info!("shrink blocklist");
 		inner_block_list.trie.shrink_to_fit();
 		info!(
 			"{} domains are blocked",
 			inner_block_list.trie.len().to_formatted_string(&Locale::en)
 		);
-		if inner_block_list.trie.len() == 0 {
+		if inner_block_list.regex.len() > 0 {
+			info!(
+				"{} regular expressions block domains",
+				inner_block_list
+					.regex
+					.len()
+					.to_formatted_string(&Locale::en)
+			);
+		}
+		if inner_block_list.trie.len() == 0 && inner_block_list.regex.len() == 0 {
 			warn!("Blocklist is empty");
 		}
+		if !config.ip_blocklist.is_empty() {
+			info!(
+				"{} ip ranges are blocked",
+				inner_block_list
+					.ip_trie
+					.len()
+					.to_formatted_string(&Locale::en)
+			);
+		}
+		inner_block_list.groups = groups
+			.iter()
+			.map(|group| GroupLists {
+				block: select(group.lists.as_ref(), &inner_block_list.block_list_info),
+				allow: select(
+					group.allow_list.as_ref(),
+					&inner_block_list.allow_list_info
+				),
+				ip: select(group.ip_blocklist.as_ref(), &inner_block_list.ip_list_info)
+			})
+			.collect();
+		let build = started.elapsed();
+		info!("built blocklist in {build:?}");
+		inner_block_list.update_durations = Some(UpdateDurations {
+			download: download.as_millis() as u64,
+			parse: parse.as_millis() as u64,
+			build: build.as_millis() as u64
+		});
+		let next_update = self.next_update(
+			list_urls(config, block_lists).map(|(url, ..)| url),
+			config.update_interval()
+		);
+		(inner_block_list, next_update)
+	}
+
+	/// Replace the current Blocklist with `inner_block_list` of [`Self::build`].
+	pub(crate) async fn replace(&self, mut inner_block_list: InnerBlockList) {
+		let custom = self.custom_lists().await;
+		inner_block_list.custom_block = custom.block.trie();
+		inner_block_list.custom_allow = custom.allow.trie();
 		let mut guard = self.rw_lock.write().await;
 		*guard = inner_block_list;
 		drop(guard);
+		drop(custom);
 		info!("👮✅ finish updating blocklist");
 	}
 
-	/// return true if domain is blocked
-	pub(crate) async fn blocked(&self, domain: &str, include_subdomains: bool) -> bool {
-		self.rw_lock
-			.read()
-			.await
-			.trie
-			.blocked(domain, include_subdomains)
+	/// return the names (or urls) of the lists, which block the domain for the client group `group`.
+	/// Return `None` if the domain is not blocked.
+	/// The regex lists are only checked, if the domain is not blocked by the trie.
+	pub(crate) async fn blocked(
+		&self,
+		domain: &str,
+		group: Option<usize>
+	) -> Option<Vec<String>> {
+		let guard = self.rw_lock.read().await;
+		// custom entries ignore the client groups
+		let exact = BitVec::new();
+		let custom_filter = ListFilter {
+			subdomains: Some(&exact),
+			..Default::default()
+		};
+		if guard
+			.custom_allow
+			.blocked_by(domain, custom_filter)
+			.is_some()
+		{
+			return None;
+		}
+		if guard
+			.custom_block
+			.blocked_by(domain, custom_filter)
+			.is_some()
+		{
+			return Some(vec![CUSTOM.to_owned()]);
+		}
+		// lists with an inactive schedule are removed from the filter,
+		// so the trie does not need to be rebuilt if a schedule changes
+		let active = guard.active_lists(group);
+		let filter = guard.filter(group, active.as_ref());
+		if guard.allow_regex.is_match(domain, filter.allow) {
+			return None;
+		}
+		let Some((trie_value, parent)) = guard.trie.blocked_by(domain, filter) else {
+			if guard.trie.allowed_by(domain, filter) {
+				return None;
+			}
+			let lists: Vec<_> = guard
+				.regex
+				.matches(domain, filter.block)
+				.filter_map(|(regex, i)| {
+					let list_info = guard.block_list_info.get(i)?;
+					Some(format!("{} ({regex})", list_info.label()))
+				})
+				.collect();
+			return (!lists.is_empty()).then_some(lists);
+		};
+		Some(
+			trie_value
+				.lists(filter, parent)
+				.filter_map(|i| guard.block_list_info.get(i))
+				.map(|list_info| list_info.label().to_owned())
+				.collect()
+		)
+	}
+
+	/// return the addresses of `domain` from the hosts files,
+	/// which are selected by the client group `group`.
+	pub(crate) async fn hosts(
+		&self,
+		domain: &str,
+		group: Option<usize>
+	) -> Option<Vec<IpAddr>> {
+		let guard = self.rw_lock.read().await;
+		let selected = guard.filter(group, None).block;
+		let mut ips = Vec::new();
+		for (ip, list_info_index) in guard.hosts.get(domain)? {
+			if selected.is_none_or(|lists| lists.get(*list_info_index) == Some(true))
+				&& !ips.contains(ip)
+			{
+				ips.push(*ip);
+			}
+		}
+		(!ips.is_empty()).then_some(ips)
+	}
+
+	/// return the names (or urls) of the ip lists, which block `ip` for the client group `group`.
+	/// Return `None` if `ip` is not blocked.
+	pub(crate) async fn ip_blocked(
+		&self,
+		ip: IpAddr,
+		group: Option<usize>
+	) -> Option<Vec<String>> {
+		let guard = self.rw_lock.read().await;
+		let selected = group
+			.and_then(|group| guard.groups.get(group))
+			.and_then(|group| group.ip.as_ref());
+		let block_source = guard.ip_trie.blocked_by(ip, selected)?;
+		Some(
+			block_source
+				.iter()
+				.enumerate()
+				.filter(|(i, is_in)| {
+					*is_in && selected.is_none_or(|ip| ip.get(*i) == Some(true))
+				})
+				.filter_map(|(i, _)| guard.ip_list_info.get(i))
+				.map(|list_info| list_info.label().to_owned())
+				.collect()
+		)
 	}
 
 	// #################### api helper functions ####################
 
+	/// return the entries of the custom list `tipe`
+	pub(crate) async fn custom(&self, tipe: CustomListType) -> Vec<CustomEntry> {
+		self.custom_lists().await.get_mut(tipe).entries.clone()
+	}
+
+	/// Add `entry` to the custom list `tipe`, save the list
+	/// and apply it to the running blocklist.
+	/// The list is not changed, if it can not be saved.
+	pub(crate) async fn add_custom(
+		&self,
+		tipe: CustomListType,
+		entry: CustomEntry
+	) -> anyhow::Result<()> {
+		let mut custom = self.custom_lists().await;
+		let list = custom.get_mut(tipe);
+		let entries = list.entries.clone();
+		list.add(entry);
+		if let Err(err) = list.save().await {
+			list.entries = entries;
+			return Err(err);
+		}
+		self.apply_custom(tipe, &custom).await;
+		Ok(())
+	}
+
+	/// Remove the entry of `domain` from the custom list `tipe`, save the list
+	/// and apply it to the running blocklist.
+	/// The list is not changed, if it can not be saved.
+	/// Return false if the list does not contain `domain`.
+	pub(crate) async fn remove_custom(
+		&self,
+		tipe: CustomListType,
+		domain: &str
+	) -> anyhow::Result<bool> {
+		let mut custom = self.custom_lists().await;
+		let list = custom.get_mut(tipe);
+		let entries = list.entries.clone();
+		if !list.remove(domain) {
+			return Ok(false);
+		}
+		if let Err(err) = list.save().await {
+			list.entries = entries;
+			return Err(err);
+		}
+		self.apply_custom(tipe, &custom).await;
+		Ok(true)
+	}
+
+	/// Replace the entries of the custom list `tipe` of the running blocklist.
+	async fn apply_custom(&self, tipe: CustomListType, custom: &CustomLists) {
+		let mut guard = self.rw_lock.write().await;
+		match tipe {
+			CustomListType::Block => guard.custom_block = custom.block.trie(),
+			CustomListType::Allow => guard.custom_allow = custom.allow.trie()
+		}
+	}
+
 	/// return info about all blocklist
-	pub(crate) async fn list<'a>(&self) -> Vec<api::List> {
+	pub(crate) async fn list(&self) -> Vec<api::List> {
 		let guard = self.rw_lock.read().await;
 		guard
 			.block_list_info
 			.iter()
-			.map(|f| (f, ListType::Block))
-			.chain(guard.allow_list_info.iter().map(|f| (f, ListType::Allow)))
-			.map(|(list, tipe)| {
+			.chain(&guard.allow_list_info)
+			.chain(&guard.ip_list_info)
+			.map(|list| {
+				let tipe = list.tipe.clone();
 				if let Some(errors) = &list.error {
 					api::List::UpdateFailed(api::UpdateFailedList {
 						len: list.len,
 						url: list.url.to_owned(),
+						name: list.name.clone(),
 						error: errors.to_owned(),
-						tipe
+						tipe,
+						next_update: self.next_refresh(&list.url),
+						modified: list.modified
 					})
 				} else {
 					api::List::Ok(api::OkList {
 						len: list.len,
 						url: list.url.to_owned(),
-						tipe
+						name: list.name.clone(),
+						tipe,
+						next_update: self.next_refresh(&list.url),
+						modified: list.modified
 					})
 				}
 			})
-			.chain(
-				guard
-					.failed_lists
-					.iter()
-					.map(|f| api::List::Error(f.clone()))
-			)
+			.chain(guard.failed_lists.iter().map(|f| {
+				api::List::Error(FailedList {
+					next_update: self.next_refresh(&f.url),
+					..f.clone()
+				})
+			}))
 			.collect()
 	}
 
+	pub(crate) async fn update_durations(&self) -> Option<UpdateDurations> {
+		self.rw_lock.read().await.update_durations.clone()
+	}
+
 	pub(crate) async fn len(&self) -> usize {
-		self.rw_lock.read().await.trie.len()
+		let guard = self.rw_lock.read().await;
+		guard.trie.len() + guard.regex.len()
 	}
 
 	/// querry all block and allow entrys assiated with `domain` including subdomains.
-	/// retrun the listinfo, allowed_state and start pos of the match
+	/// retrun the listinfo, allowed_state and start pos of the match.
+	/// Matches of regex lists are reported for the whole domain.
 	pub(crate) async fn query(&self, domain: &str) -> HashMap<String, QueryInfo> {
 		let guard = self.rw_lock.read().await;
 		let mut hits = HashMap::new();
 		for (trie_value, pos) in &guard.trie.query(domain) {
 			let mut query_info = QueryInfo {
-				lists: Vec::new(),
-				allowed: trie_value.allowed
+				allowed: trie_value.allowed(),
+				..Default::default()
 			};
 			for (i, is_in) in trie_value.block_source.iter().enumerate() {
 				if is_in {
-					let list_info = guard.block_list_info.get(i).unwrap();
-					query_info.lists.push(list_info.url.clone());
+					query_info.push_list(guard.block_list_info.get(i).unwrap());
 				}
 			}
 			hits.insert((domain[*pos ..]).to_owned(), query_info);
 		}
+		let custom_hits = guard
+			.custom_block
+			.query(domain)
+			.into_iter()
+			.map(|hit| (hit, false))
+			.chain(
+				guard
+					.custom_allow
+					.query(domain)
+					.into_iter()
+					.map(|hit| (hit, true))
+			);
+		for ((_, pos), allowed) in custom_hits {
+			let query_info = hits.entry((domain[pos ..]).to_owned()).or_default();
+			if allowed {
+				query_info.allowed = true;
+			} else {
+				query_info.push(CUSTOM, CUSTOM);
+			}
+		}
+		let regex_hits = guard
+			.regex
+			.matches(domain, None)
+			.map(|(regex, i)| (regex, &guard.block_list_info[i], false))
+			.chain(
+				guard
+					.allow_regex
+					.matches(domain, None)
+					.map(|(regex, i)| (regex, &guard.allow_list_info[i], true))
+			);
+		for (regex, list_info, allowed) in regex_hits {
+			let query_info = hits.entry(domain.to_owned()).or_default();
+			if allowed {
+				query_info.allowed = true;
+			} else {
+				query_info.push_list(list_info);
+			}
+			query_info.regex.push(regex.to_owned());
+		}
 		hits
 	}
 }
 
-#[derive(Debug, poem_openapi::Object)]
+#[derive(Debug, Default, poem_openapi::Object)]
 pub(crate) struct QueryInfo {
 	/// url of the blocklists, which blocks the domain
 	lists: Vec<String>,
+	/// names of the blocklists of `lists` in the same order.
+	/// The url is used for lists without name.
+	names: Vec<String>,
 	/// indicate if the access to the matched domain is blocked
 	/// or was allowed by a allowlist
-	allowed: bool
+	allowed: bool,
+	/// regular expressions of the regex lists, which match the domain
+	regex: Vec<String>
+}
+
+impl QueryInfo {
+	/// Add the blocklist `list_info`, if it was not already added.
+	fn push_list(&mut self, list_info: &ListInfo) {
+		self.push(&list_info.url, list_info.label());
+	}
+
+	/// Add the blocklist `url` with the name `label`, if it was not already added.
+	fn push(&mut self, url: &str, label: &str) {
+		if !self.lists.iter().any(|list| list == url) {
+			self.lists.push(url.to_owned());
+			self.names.push(label.to_owned());
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn schedule() {
+		let blocklist = BlockList::new();
+		let url = Url::parse("https://example.com/hosts").unwrap();
+		let hour = Duration::from_secs(60 * 60);
+		let delay = |blocklist: &BlockList| {
+			let refresh = blocklist.refresh.lock().unwrap();
+			let next = refresh[url.as_str()].next.duration_since(Instant::now());
+			// round up the time passed since scheduling
+			Duration::from_secs(next.as_secs() + 1)
+		};
+		assert!(!blocklist.use_cache(&url, false));
+		for backoff in [1, 2, 4, 8, 16, 32, 60, 60] {
+			blocklist.schedule(&url, hour, Duration::ZERO, true);
+			assert_eq!(delay(&blocklist), backoff * RETRY_INTERVAL);
+		}
+		assert!(blocklist.use_cache(&url, false));
+		blocklist.schedule(&url, hour, hour, false);
+		assert!((hour ..= 2 * hour).contains(&delay(&blocklist)));
+		blocklist.schedule(&url, hour, Duration::ZERO, true);
+		assert_eq!(delay(&blocklist), RETRY_INTERVAL);
+	}
+
+	#[tokio::test]
+	async fn modified() {
+		let path = std::env::temp_dir()
+			.join(format!("crab-hole-modified-{}.txt", std::process::id()));
+		let url = Url::from_file_path(&path).unwrap();
+		let blocklist = BlockList::new();
+		let config = BlockConfig::default();
+		// the same url is used by two block lists and a hosts file
+		let modified = || async {
+			let lists = vec![
+				(url.clone(), ListType::Block, None),
+				(url.clone(), ListType::Block, None),
+				(url.clone(), ListType::Hosts, None),
+			];
+			blocklist
+				.download(lists, &config, false)
+				.await
+				.iter()
+				.map(|list| list.modified)
+				.collect::<Vec<_>>()
+		};
+		std::fs::write(&path, "example.com\n").unwrap();
+		assert_eq!(modified().await, [true; 3]);
+		assert_eq!(modified().await, [false; 3]);
+		std::fs::write(&path, "example.org\n").unwrap();
+		assert_eq!(modified().await, [true; 3]);
+		std::fs::remove_file(&path).unwrap();
+	}
+
+	#[tokio::test]
+	async fn keep() {
+		let blocklist = BlockList::new();
+		let config = BlockConfig::default();
+		let url = Url::parse("https://example.com/hosts").unwrap();
+		let raw_list = |modified| RawList {
+			url: url.clone(),
+			tipe: ListType::Block,
+			interval: None,
+			restored: false,
+			raw: Some(String::new()),
+			errors: String::new(),
+			modified
+		};
+		// the first update always builds the blocklist
+		assert!(!blocklist.keep(&config, &[raw_list(false)]).await);
+		blocklist
+			.replace(InnerBlockList {
+				update_durations: Some(UpdateDurations {
+					download: 0,
+					parse: 0,
+					build: 0
+				}),
+				..Default::default()
+			})
+			.await;
+		assert!(!blocklist.keep(&config, &[raw_list(true)]).await);
+		assert!(blocklist.refresh.lock().unwrap().is_empty());
+		assert!(blocklist.keep(&config, &[raw_list(false)]).await);
+		assert!(blocklist.refresh.lock().unwrap().contains_key(url.as_str()));
+		let failed = RawList {
+			raw: None,
+			..raw_list(false)
+		};
+		assert!(!blocklist.keep(&config, &[failed]).await);
+	}
+
+	#[test]
+	fn compile_regex_failure() {
+		let mut list_info: Vec<_> = (0 .. 3)
+			.map(|i| ListInfo {
+				len: 1,
+				url: format!("https://example.com/regex{i}"),
+				name: None,
+				tipe: ListType::Regex,
+				error: None,
+				modified: false
+			})
+			.collect();
+		let set = compile_regex(
+			vec![
+				("^a\\.".to_owned(), 0),
+				("(".to_owned(), 1),
+				("^b\\.".to_owned(), 2),
+			],
+			&mut list_info
+		);
+		assert_eq!(set.len(), 2);
+		assert!(set.is_match("b.example.com", None));
+		assert!(list_info[0].error.is_none());
+		assert!(list_info[1].error.is_some());
+		assert_eq!(list_info[1].len, 0);
+		assert!(list_info[2].error.is_none());
+	}
+
+	#[tokio::test]
+	async fn list_types() {
+		let dir =
+			std::env::temp_dir().join(format!("crab-hole-types-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let files = [
+			("block.txt", "blocked.example.com\nallowed.example.com\n"),
+			("hosts.txt", "192.168.1.10 nas.home\n"),
+			("regex.txt", "^ads\\.\n"),
+			("ip.txt", "10.66.0.0/16\n"),
+			("allow.txt", "allowed.example.com\n"),
+			("regex-allow.txt", "^ads\\.example\\.org$\n")
+		];
+		for (name, content) in files {
+			std::fs::write(dir.join(name), content).unwrap();
+		}
+		let url = |name: &str| format!("\"file://{}\"", dir.join(name).display());
+		// every type has a different count of lists, so misaligned lists are parsed wrong
+		let config: BlockConfig = toml::from_str(&format!(
+			r#"
+			lists = [{block}, {block}, {block}]
+			include_subdomains = false
+			hosts = [{hosts}, {hosts}]
+			regex_lists = [{regex}]
+			ip_blocklist = [{ip}, {ip}, {ip}, {ip}]
+			allow_list = [{allow}]
+			regex_allow_list = [{regex_allow}, {regex_allow}]
+			"#,
+			block = url("block.txt"),
+			hosts = url("hosts.txt"),
+			regex = url("regex.txt"),
+			ip = url("ip.txt"),
+			allow = url("allow.txt"),
+			regex_allow = url("regex-allow.txt")
+		))
+		.unwrap();
+		let blocklist = BlockList::new();
+		blocklist.update(&config, &[], false).await;
+		std::fs::remove_dir_all(&dir).unwrap();
+		assert!(blocklist.rw_lock.read().await.failed_lists.is_empty());
+		assert_eq!(
+			blocklist
+				.blocked("blocked.example.com", None)
+				.await
+				.unwrap()
+				.len(),
+			3
+		);
+		assert!(blocklist
+			.blocked("allowed.example.com", None)
+			.await
+			.is_none());
+		assert!(blocklist.blocked("ads.example.com", None).await.is_some());
+		assert!(blocklist.blocked("ads.example.org", None).await.is_none());
+		assert!(blocklist.hosts("nas.home", None).await.is_some());
+		let ip = "10.66.1.1".parse().unwrap();
+		assert_eq!(blocklist.ip_blocked(ip, None).await.unwrap().len(), 4);
+	}
+
+	#[tokio::test]
+	async fn regex_wildcard_allow() {
+		let dir =
+			std::env::temp_dir().join(format!("crab-hole-regex-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let regex = dir.join("regex.txt");
+		let allow = dir.join("allow.txt");
+		std::fs::write(&regex, "^ads\\.\n").unwrap();
+		std::fs::write(&allow, "*.example.com\n").unwrap();
+		let config: BlockConfig = toml::from_str(&format!(
+			r#"
+			lists = []
+			include_subdomains = false
+			allow_list = ["file://{}"]
+			regex_lists = ["file://{}"]
+			"#,
+			allow.display(),
+			regex.display()
+		))
+		.unwrap();
+		let blocklist = BlockList::new();
+		blocklist.update(&config, &[], false).await;
+		std::fs::remove_dir_all(&dir).unwrap();
+		assert!(blocklist.blocked("ads.example.org", None).await.is_some());
+		assert!(blocklist.blocked("ads.example.com", None).await.is_none());
+		assert!(blocklist
+			.blocked("ads.sub.example.com", None)
+			.await
+			.is_none());
+	}
+
+	#[tokio::test]
+	async fn hosts_group() {
+		let dir =
+			std::env::temp_dir().join(format!("crab-hole-hosts-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let home = dir.join("home.txt");
+		let office = dir.join("office.txt");
+		std::fs::write(&home, "192.168.1.10 nas.home\n").unwrap();
+		std::fs::write(&office, "10.0.0.10 nas.home\n10.0.0.11 printer.office\n")
+			.unwrap();
+		let url = |path: &std::path::Path| format!("file://{}", path.display());
+		let config: BlockConfig = toml::from_str(&format!(
+			r#"
+			lists = []
+			include_subdomains = false
+			hosts = ["{}", "{}"]
+			"#,
+			url(&home),
+			url(&office)
+		))
+		.unwrap();
+		let groups: Vec<GroupConfig> =
+			toml::from_str::<HashMap<String, Vec<GroupConfig>>>(&format!(
+				r#"
+				[[groups]]
+				name = "office"
+				clients = []
+				lists = ["{}"]
+				"#,
+				url(&office)
+			))
+			.unwrap()
+			.remove("groups")
+			.unwrap();
+		let blocklist = BlockList::new();
+		blocklist.update(&config, &groups, false).await;
+		std::fs::remove_dir_all(&dir).unwrap();
+		let ips = |ips: &[&str]| -> Option<Vec<IpAddr>> {
+			Some(ips.iter().map(|ip| ip.parse().unwrap()).collect())
+		};
+		assert_eq!(
+			blocklist.hosts("nas.home", None).await,
+			ips(&["192.168.1.10", "10.0.0.10"])
+		);
+		assert_eq!(
+			blocklist.hosts("nas.home", Some(0)).await,
+			ips(&["10.0.0.10"])
+		);
+		assert!(blocklist.hosts("printer.office", Some(0)).await.is_some());
+	}
 }
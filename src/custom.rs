@@ -0,0 +1,184 @@
+use crate::{parser, trie::Trie, LIST_DIR};
+use anyhow::{anyhow, bail, Context};
+use log::error;
+use poem_openapi::Object;
+use std::{io::ErrorKind, path::PathBuf};
+use tokio::fs::{create_dir_all, read_to_string, write};
+
+/// Custom lists, whose entries are managed by the api.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, poem_openapi::Enum)]
+#[oai(rename_all = "lowercase")]
+pub(crate) enum CustomListType {
+	Block,
+	Allow
+}
+
+/// Entry of a custom list.
+#[derive(Clone, Debug, PartialEq, Eq, Object)]
+pub(crate) struct CustomEntry {
+	/// `<domain>` or `*.<domain>`, which matches the subdomains too
+	pub(crate) domain: String,
+	pub(crate) comment: Option<String>
+}
+
+impl CustomEntry {
+	/// Parse a single entry like `example.com`, `*.example.com` or `||example.com^`.
+	pub(crate) fn new(entry: &str, comment: Option<&str>) -> anyhow::Result<Self> {
+		let list = parser::Blocklist::parse("<entry>", entry.trim())
+			.map_err(|err| anyhow!("invalid entry {entry:?}\n{}", err.msg()))?;
+		let domain = match &list.entries[..] {
+			[parser::Line::Domain(domain)] => domain.0.to_lowercase(),
+			[parser::Line::Wildcard(domain)] => format!("*.{}", domain.0.to_lowercase()),
+			_ => bail!("invalid entry {entry:?}, expected a single domain")
+		};
+		// the comment is stored in the same line as the entry
+		let comment = comment
+			.map(|comment| comment.split_whitespace().collect::<Vec<_>>().join(" "))
+			.filter(|comment| !comment.is_empty());
+		Ok(Self { domain, comment })
+	}
+
+	/// Return the domain of the entry and true if it matches the subdomains too.
+	fn domain(&self) -> (&str, bool) {
+		match self.domain.strip_prefix("*.") {
+			Some(domain) => (domain, true),
+			None => (&self.domain, false)
+		}
+	}
+}
+
+/// Custom list, which is stored at `LIST_DIR/custom/<type>.txt`.
+#[derive(Debug)]
+pub(crate) struct CustomList {
+	path: PathBuf,
+	pub(crate) entries: Vec<CustomEntry>
+}
+
+impl CustomList {
+	/// Load the custom list `tipe`. The list is empty, if it was not saved before.
+	pub(crate) async fn load(tipe: CustomListType) -> Self {
+		let name = match tipe {
+			CustomListType::Block => "block",
+			CustomListType::Allow => "allow"
+		};
+		let path = LIST_DIR.join("custom").join(format!("{name}.txt"));
+		let raw = match read_to_string(&path).await {
+			Ok(raw) => raw,
+			Err(err) => {
+				if err.kind() != ErrorKind::NotFound {
+					error!("failed to read {path:?}: {err}");
+				}
+				String::new()
+			}
+		};
+		let entries = raw
+			.lines()
+			.map(str::trim)
+			.filter(|line| !line.is_empty() && !line.starts_with('#'))
+			.filter_map(|line| {
+				let (entry, comment) = match line.split_once('#') {
+					Some((entry, comment)) => (entry, Some(comment)),
+					None => (line, None)
+				};
+				CustomEntry::new(entry, comment)
+					.inspect_err(|err| error!("{path:?}: {err}"))
+					.ok()
+			})
+			.collect();
+		Self { path, entries }
+	}
+
+	/// Write the list to its file.
+	pub(crate) async fn save(&self) -> anyhow::Result<()> {
+		if let Some(dir) = self.path.parent() {
+			create_dir_all(dir)
+				.await
+				.with_context(|| format!("failed create dir {dir:?}"))?;
+		}
+		let mut raw = String::from("# managed by the api of crab-hole\n");
+		for entry in &self.entries {
+			raw += &entry.domain;
+			if let Some(comment) = &entry.comment {
+				raw += " # ";
+				raw += comment;
+			}
+			raw.push('\n');
+		}
+		write(&self.path, raw)
+			.await
+			.with_context(|| format!("failed to save to {:?}", self.path))
+	}
+
+	/// Add `entry` or replace the comment of an existing entry with the same domain.
+	pub(crate) fn add(&mut self, entry: CustomEntry) {
+		match self
+			.entries
+			.iter_mut()
+			.find(|old| old.domain == entry.domain)
+		{
+			Some(old) => *old = entry,
+			None => self.entries.push(entry)
+		}
+	}
+
+	/// Remove the entry of `domain`. Return false if the list does not contain it.
+	pub(crate) fn remove(&mut self, domain: &str) -> bool {
+		let len = self.entries.len();
+		self.entries.retain(|entry| entry.domain != domain);
+		self.entries.len() != len
+	}
+
+	/// Return a trie containing the entries as list 0.
+	/// Entries without wildcard do not match their subdomains.
+	pub(crate) fn trie(&self) -> Trie {
+		let mut trie = Trie::new();
+		for entry in &self.entries {
+			let (domain, include_subdomains) = entry.domain();
+			trie.insert(domain, 0, include_subdomains);
+		}
+		trie
+	}
+}
+
+/// The custom block and allow list.
+#[derive(Debug)]
+pub(crate) struct CustomLists {
+	pub(crate) block: CustomList,
+	pub(crate) allow: CustomList
+}
+
+impl CustomLists {
+	pub(crate) async fn load() -> Self {
+		Self {
+			block: CustomList::load(CustomListType::Block).await,
+			allow: CustomList::load(CustomListType::Allow).await
+		}
+	}
+
+	pub(crate) fn get_mut(&mut self, tipe: CustomListType) -> &mut CustomList {
+		match tipe {
+			CustomListType::Block => &mut self.block,
+			CustomListType::Allow => &mut self.allow
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn entry() {
+		let entry = CustomEntry::new("||Example.com^", Some(" broken\nshop ")).unwrap();
+		assert_eq!(entry.domain, "*.example.com");
+		assert_eq!(entry.comment.as_deref(), Some("broken shop"));
+		assert_eq!(entry.domain(), ("example.com", true));
+		assert!(CustomEntry::new("example.com", Some(" "))
+			.unwrap()
+			.comment
+			.is_none());
+		assert!(CustomEntry::new("", None).is_err());
+		assert!(CustomEntry::new("0.0.0.0 example.com", None).is_err());
+		assert!(CustomEntry::new("a.com\nb.com", None).is_err());
+	}
+}
@@ -0,0 +1,126 @@
+use crate::trie::{intersects, set_source};
+use bit_vec::BitVec;
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+use prefix_trie::PrefixMap;
+use std::net::IpAddr;
+
+/// Blocked ip ranges.
+/// Like the domain [`Trie`](crate::trie::Trie), each range stores a [`BitVec`],
+/// whose `true`s are the indices of the lists, which contain the range.
+#[derive(Debug, Default)]
+pub(crate) struct IpTrie {
+	v4: PrefixMap<Ipv4Net, BitVec>,
+	v6: PrefixMap<Ipv6Net, BitVec>
+}
+
+impl IpTrie {
+	pub(crate) fn new() -> Self {
+		Self::default()
+	}
+
+	/// Add an ip range to this trie.
+	/// Return true if the range was already added by the same list.
+	pub(crate) fn insert(&mut self, net: IpNet, list_info_index: usize) -> bool {
+		match net.trunc() {
+			IpNet::V4(net) => {
+				set_source(self.v4.entry(net).or_default(), list_info_index)
+			},
+			IpNet::V6(net) => set_source(self.v6.entry(net).or_default(), list_info_index)
+		}
+	}
+
+	/// Return the sources of the most specific range, which contains `ip`
+	/// and was added by one of the lists selected by `filter`.
+	/// IPv4-mapped IPv6 addresses are checked against the IPv4 ranges.
+	/// Return `None` if `ip` is not blocked.
+	pub(crate) fn blocked_by(
+		&self,
+		ip: IpAddr,
+		filter: Option<&BitVec>
+	) -> Option<&BitVec> {
+		let selected = |block_source: &&BitVec| intersects(block_source, filter);
+		match ip.to_canonical() {
+			IpAddr::V4(ip) => self
+				.v4
+				.cover_values(&Ipv4Net::from(ip))
+				.filter(selected)
+				.last(),
+			IpAddr::V6(ip) => self
+				.v6
+				.cover_values(&Ipv6Net::from(ip))
+				.filter(selected)
+				.last()
+		}
+	}
+
+	pub(crate) fn len(&self) -> usize {
+		self.v4.len() + self.v6.len()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn v4() {
+		let mut trie = IpTrie::new();
+		assert!(!trie.insert("192.168.1.0/24".parse().unwrap(), 0));
+		assert!(trie.insert("192.168.1.0/24".parse().unwrap(), 0));
+		assert!(trie
+			.blocked_by("192.168.1.42".parse().unwrap(), None)
+			.is_some());
+		assert!(trie
+			.blocked_by("192.168.2.1".parse().unwrap(), None)
+			.is_none());
+		assert!(trie
+			.blocked_by("::ffff:192.168.1.42".parse().unwrap(), None)
+			.is_some());
+	}
+
+	#[test]
+	fn v6() {
+		let mut trie = IpTrie::new();
+		trie.insert("2001:db8::/32".parse().unwrap(), 1);
+		trie.insert("2001:db8:1::/48".parse().unwrap(), 0);
+		assert_eq!(
+			trie.blocked_by("2001:db8:1::1".parse().unwrap(), None)
+				.unwrap()
+				.iter()
+				.collect::<Vec<_>>(),
+			vec![true]
+		);
+		assert_eq!(
+			trie.blocked_by("2001:db8:2::1".parse().unwrap(), None)
+				.unwrap()
+				.iter()
+				.collect::<Vec<_>>(),
+			vec![false, true]
+		);
+		assert!(trie
+			.blocked_by("2001:db9::1".parse().unwrap(), None)
+			.is_none());
+		// the /48 range is not selected, so the /32 range of the second list is used
+		let second = BitVec::from_fn(2, |i| i == 1);
+		assert_eq!(
+			trie.blocked_by("2001:db8:1::1".parse().unwrap(), Some(&second))
+				.unwrap()
+				.iter()
+				.collect::<Vec<_>>(),
+			vec![false, true]
+		);
+		assert!(trie
+			.blocked_by("2001:db8:1::1".parse().unwrap(), Some(&BitVec::new()))
+			.is_none());
+	}
+
+	#[test]
+	fn host_bits() {
+		let mut trie = IpTrie::new();
+		trie.insert("10.0.0.1/8".parse().unwrap(), 0);
+		assert!(trie
+			.blocked_by("10.255.0.1".parse().unwrap(), None)
+			.is_some());
+		assert_eq!(trie.len(), 1);
+	}
+}
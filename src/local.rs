@@ -0,0 +1,242 @@
+use crate::response::soa;
+use anyhow::{anyhow, Context};
+use hickory_proto::rr::{
+	rdata::{A, AAAA, CNAME, PTR, SRV, TXT},
+	Name, RData, Record, RecordType
+};
+use hickory_server::{authority::ZoneType, store::in_memory::InMemoryAuthority};
+use log::warn;
+use serde::Deserialize;
+use std::{
+	collections::{BTreeMap, HashSet},
+	net::IpAddr,
+	str::FromStr
+};
+
+/// Record types, which can be used at the `[[local_records]]` config section.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+enum LocalRecordType {
+	A,
+	Aaaa,
+	Cname,
+	Txt,
+	Ptr,
+	Srv
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawLocalRecord {
+	name: String,
+	#[serde(rename = "type")]
+	record_type: LocalRecordType,
+	/// address, target name, text or `<priority> <weight> <port> <target>` for SRV
+	value: String,
+	#[serde(default = "default_ttl")]
+	ttl: u32
+}
+
+fn default_ttl() -> u32 {
+	300
+}
+
+/// A record of the `[[local_records]]` config section.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "RawLocalRecord")]
+pub(crate) struct LocalRecord(Record);
+
+/// Parse a domain name as fully qualified name.
+fn fqdn(name: &str) -> anyhow::Result<Name> {
+	let mut name =
+		Name::from_str(name).with_context(|| format!("invalid domain name {name:?}"))?;
+	name.set_fqdn(true);
+	Ok(name)
+}
+
+impl TryFrom<RawLocalRecord> for LocalRecord {
+	type Error = String;
+
+	fn try_from(raw: RawLocalRecord) -> Result<Self, Self::Error> {
+		let rdata = || -> anyhow::Result<RData> {
+			let value = raw.value.as_str();
+			Ok(match raw.record_type {
+				LocalRecordType::A => RData::A(A(value.parse()?)),
+				LocalRecordType::Aaaa => RData::AAAA(AAAA(value.parse()?)),
+				LocalRecordType::Cname => RData::CNAME(CNAME(fqdn(value)?)),
+				LocalRecordType::Txt => RData::TXT(TXT::new(vec![raw.value.clone()])),
+				LocalRecordType::Ptr => RData::PTR(PTR(fqdn(value)?)),
+				LocalRecordType::Srv => {
+					let [priority, weight, port, target] = value
+						.split_whitespace()
+						.collect::<Vec<_>>()
+						.try_into()
+						.map_err(|_| {
+							anyhow!("expected \"<priority> <weight> <port> <target>\"")
+						})?;
+					RData::SRV(SRV::new(
+						priority.parse()?,
+						weight.parse()?,
+						port.parse()?,
+						fqdn(target)?
+					))
+				}
+			})
+		};
+		let name = fqdn(&raw.name).map_err(|err| format!("{err:#}"))?;
+		let rdata = rdata().map_err(|err| {
+			format!(
+				"invalid value {:?} of {:?} record {name}: {err:#}",
+				raw.value, raw.record_type
+			)
+		})?;
+		Ok(Self(Record::from_rdata(name, raw.ttl, rdata)))
+	}
+}
+
+/// Create an authority for each name of `records`,
+/// which serves the records of this name.
+/// PTR records are added for the addresses of A and AAAA records,
+/// if no PTR record was configured for the address.
+pub(crate) fn authorities(records: &[LocalRecord]) -> Vec<InMemoryAuthority> {
+	let configured_ptrs: HashSet<&Name> = records
+		.iter()
+		.filter(|record| record.0.record_type() == RecordType::PTR)
+		.map(|record| record.0.name())
+		.collect();
+	let ptrs = records.iter().filter_map(|record| {
+		let ip = match record.0.data() {
+			RData::A(a) => IpAddr::V4(a.0),
+			RData::AAAA(aaaa) => IpAddr::V6(aaaa.0),
+			_ => return None
+		};
+		let name = Name::from(ip);
+		(!configured_ptrs.contains(&name)).then(|| {
+			Record::from_rdata(
+				name,
+				record.0.ttl(),
+				RData::PTR(PTR(record.0.name().clone()))
+			)
+		})
+	});
+
+	let mut zones: BTreeMap<Name, Vec<Record>> = BTreeMap::new();
+	for record in records.iter().map(|record| record.0.clone()).chain(ptrs) {
+		zones.entry(record.name().clone()).or_default().push(record);
+	}
+
+	zones
+		.into_iter()
+		.map(|(name, records)| {
+			let mut authority = InMemoryAuthority::empty(
+				name.clone(),
+				ZoneType::Primary,
+				false,
+				#[cfg(any(feature = "ring", feature = "aws-lc-rs"))]
+				None
+			);
+			// a CNAME can not coexist with other records
+			if !records
+				.iter()
+				.any(|record| record.record_type() == RecordType::CNAME)
+			{
+				let minimum = records.iter().map(Record::ttl).min().unwrap_or_default();
+				authority.upsert_mut(soa(name, minimum), 1);
+			}
+			for record in records {
+				if !authority.upsert_mut(record.clone(), 1) {
+					warn!("ignore local record {record}, which conflicts with other records");
+				}
+			}
+			authority
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use hickory_server::authority::Authority;
+	use std::collections::HashMap;
+
+	fn records(config: &str) -> Result<Vec<LocalRecord>, toml::Error> {
+		toml::from_str::<HashMap<String, Vec<LocalRecord>>>(config)
+			.map(|mut table| table.remove("local_records").unwrap_or_default())
+	}
+
+	#[test]
+	fn parse() {
+		let records = records(
+			r#"
+			local_records = [
+				{ name = "nas.home", type = "A", value = "192.168.1.10" },
+				{ name = "sip.home", type = "SRV", value = "10 5 5060 nas.home", ttl = 60 },
+				{ name = "txt.home", type = "TXT", value = "hello world" }
+			]
+			"#
+		)
+		.unwrap();
+		assert_eq!(records[0].0.name(), &Name::from_str("nas.home.").unwrap());
+		assert_eq!(records[0].0.ttl(), 300);
+		assert_eq!(
+			records[1].0.data(),
+			&RData::SRV(SRV::new(10, 5, 5060, Name::from_str("nas.home.").unwrap()))
+		);
+		assert_eq!(
+			records[2].0.data(),
+			&RData::TXT(TXT::new(vec!["hello world".to_owned()]))
+		);
+	}
+
+	#[test]
+	fn invalid() {
+		for value in [
+			r#"{ name = "nas.home", type = "A", value = "fd00::1" }"#,
+			r#"{ name = "sip.home", type = "SRV", value = "10 5 nas.home" }"#,
+			r#"{ name = "nas.home", type = "MX", value = "10 nas.home" }"#
+		] {
+			records(&format!("local_records = [{value}]")).unwrap_err();
+		}
+	}
+
+	#[tokio::test]
+	async fn ptr() {
+		let authorities = authorities(
+			&records(
+				r#"
+			local_records = [
+				{ name = "nas.home", type = "A", value = "192.168.1.10" },
+				{ name = "nas.home", type = "AAAA", value = "fd00::10" },
+				{ name = "www.home", type = "A", value = "192.168.1.11" },
+				{ name = "11.1.168.192.in-addr.arpa", type = "PTR", value = "web.home" }
+			]
+			"#
+			)
+			.unwrap()
+		);
+		let origins = authorities
+			.iter()
+			.map(|authority| authority.origin().to_string())
+			.collect::<Vec<_>>();
+		assert_eq!(origins.len(), 5);
+		assert!(origins.contains(&"10.1.168.192.in-addr.arpa.".to_owned()));
+		assert!(origins.contains(&"11.1.168.192.in-addr.arpa.".to_owned()));
+		let ptrs = authorities
+			.iter()
+			.find(|authority| {
+				authority.origin().to_string() == "11.1.168.192.in-addr.arpa."
+			})
+			.unwrap()
+			.records()
+			.await
+			.into_values()
+			.flat_map(|rrset| rrset.records_without_rrsigs().cloned().collect::<Vec<_>>())
+			.filter(|record| record.record_type() == RecordType::PTR)
+			.collect::<Vec<_>>();
+		assert_eq!(ptrs.len(), 1);
+		assert_eq!(
+			ptrs[0].data(),
+			&RData::PTR(PTR(Name::from_str("web.home.").unwrap()))
+		);
+	}
+}
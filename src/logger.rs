@@ -47,16 +47,16 @@ mod tests {
 		Lazy::force(&REGEX);
 		assert!(REGEX.replace("com.", "") == "com."); //matching this would cause to much false positive
 		assert!(REGEX.replace(".com.", "") == ".com.");
-		assert!(REGEX.replace("example.com.", "") == "");
-		assert!(REGEX.replace("ex_am-ple.com.", "") == "");
+		assert!(REGEX.replace("example.com.", "").is_empty());
+		assert!(REGEX.replace("ex_am-ple.com.", "").is_empty());
 		assert!(REGEX.replace(".example.com.", "") == ".");
 		assert!(REGEX.replace(":example.com.", "") == ":");
-		assert!(REGEX.replace("eiea.eiuuue.euu.", "") == "");
-		assert!(REGEX.replace("🐬.com.", "") == "");
-		assert!(REGEX.replace("👪.com.", "") == "");
-		assert!(REGEX.replace("♡.com.", "") == ""); //`♡` is a Math char
-		assert!(REGEX.replace("ä.com.", "") == "");
-		assert!(REGEX.replace("∫.com.", "") == ""); //is this a valid domain?
+		assert!(REGEX.replace("eiea.eiuuue.euu.", "").is_empty());
+		assert!(REGEX.replace("🐬.com.", "").is_empty());
+		assert!(REGEX.replace("👪.com.", "").is_empty());
+		assert!(REGEX.replace("♡.com.", "").is_empty()); //`♡` is a Math char
+		assert!(REGEX.replace("ä.com.", "").is_empty());
+		assert!(REGEX.replace("∫.com.", "").is_empty()); //is this a valid domain?
 		assert!(REGEX.replace(":.com.", "") == ":.com.");
 	}
 }
@@ -13,23 +13,32 @@ static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
 mod api;
 mod logger;
 mod parser;
+mod response;
 
 use anyhow::{anyhow, bail, Context};
 use async_trait::async_trait;
 use directories::ProjectDirs;
 use hickory_proto::{
-	op::{header::Header, response_code::ResponseCode},
-	rr::Name
+	op::{response_code::ResponseCode, Header, LowerQuery, Message, Query},
+	rr::{rdata::CNAME, LowerName, Name, RData, Record, RecordType},
+	serialize::binary::BinDecodable,
+	xfer::Protocol
 };
 use hickory_server::{
-	authority::{Catalog, MessageResponseBuilder},
+	authority::{Authority, Catalog, MessageRequest},
 	server::{Request, RequestHandler, ResponseHandler, ResponseInfo},
 	store::forwarder::{ForwardAuthority, ForwardConfig},
 	ServerFuture as Server
 };
+use ipnet::IpNet;
 use log::{debug, error, info, warn};
 use once_cell::sync::Lazy;
-use reqwest::Client;
+use reqwest::{
+	header::{
+		HeaderMap, HeaderName, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED
+	},
+	Client, StatusCode
+};
 use rustls::{
 	crypto::CryptoProvider,
 	server::ResolvesServerCert,
@@ -39,22 +48,27 @@ use serde::Deserialize;
 use std::{
 	env::var,
 	fs::{self, File},
-	io::BufReader,
-	iter,
+	io::{BufReader, ErrorKind},
+	mem,
+	net::IpAddr,
 	path::{Path, PathBuf},
 	sync::{
 		atomic::{AtomicU64, Ordering},
-		Arc
+		Arc, RwLock
 	},
 	time::Duration,
 	vec
 };
 use time::OffsetDateTime;
 use tokio::{
-	fs::{read_to_string, write},
+	fs::{read_to_string, remove_file, rename, write},
 	net::{TcpListener, UdpSocket},
-	time::sleep,
-	try_join
+	sync::{
+		mpsc::{unbounded_channel, UnboundedSender},
+		oneshot
+	},
+	task::JoinHandle,
+	time::{sleep, Instant}
 };
 use url::Url;
 
@@ -68,7 +82,12 @@ static PROJECT_DIRS: Lazy<ProjectDirs> = Lazy::new(|| {
 		.expect("failed to get project dirs")
 });
 static LIST_DIR: Lazy<PathBuf> = Lazy::new(|| {
-	if let Ok(var) = var(format!(
+	// tests must not use or change the lists of the user
+	if cfg!(test) {
+		std::env::temp_dir()
+			.join(format!("{CARGO_PKG_NAME}-test-{}", std::process::id()))
+			.join("lists")
+	} else if let Ok(var) = var(format!(
 		"{}_DIR",
 		CARGO_PKG_NAME.to_uppercase().replace('-', "_")
 	)) {
@@ -107,17 +126,50 @@ static CONFIG_PATH: Lazy<PathBuf> = Lazy::new(|| {
 
 static CLIENT: Lazy<Client> = Lazy::new(Client::new);
 
+/// record type of DNAME records, which is not supported by hickory
+const DNAME: u16 = 39;
+
+mod custom;
+mod ip_trie;
+mod local;
+mod pause;
+mod qtype;
+mod rate_limit;
+mod rebind;
+mod regex_set;
+mod reload;
+mod safe_search;
+mod schedule;
 mod trie;
+mod zone;
 
 mod blocklist;
 use blocklist::BlockList;
+use local::LocalRecord;
+use pause::Pause;
+use qtype::{QtypeAction, QtypeRule};
+use rate_limit::{RateLimitAction, RateLimitConfig, RateLimiter};
+use rebind::RebindConfig;
+use reload::ReloadTrigger;
+use safe_search::{SafeSearch, SafeSearchConfig};
+use schedule::Schedule;
+use zone::ZoneConfig;
 
-use crate::logger::init_logger;
+use crate::{
+	logger::init_logger,
+	response::{Answer, BlockResponse, Capture, EdeCode}
+};
 
 #[derive(Debug, Clone)]
 struct Stats {
 	total_request: Arc<AtomicU64>,
 	blocked_request: Arc<AtomicU64>,
+	/// requests, which were not handled, because their client was over its rate limit
+	rate_limited_request: Arc<AtomicU64>,
+	/// requests, which matched a qtype rule
+	qtype_rule_request: Arc<AtomicU64>,
+	/// requests, whose forwarded answer contained private addresses removed by the rebind protection
+	rebind_request: Arc<AtomicU64>,
 	running_since: OffsetDateTime
 }
 
@@ -126,6 +178,9 @@ impl Default for Stats {
 		Self {
 			total_request: Default::default(),
 			blocked_request: Default::default(),
+			rate_limited_request: Default::default(),
+			qtype_rule_request: Default::default(),
+			rebind_request: Default::default(),
 			running_since: OffsetDateTime::now_utc()
 		}
 	}
@@ -134,34 +189,531 @@ impl Default for Stats {
 struct Handler {
 	catalog: Catalog,
 	blocklist: Arc<BlockList>,
-	include_subdomains: bool,
+	block_response: BlockResponse,
+	check_cname: bool,
+	groups: Vec<GroupConfig>,
+	/// index of the group of the clients without active group
+	default_group: Option<usize>,
+	/// pauses of blocking, which are started by the api
+	pause: Arc<Pause>,
+	qtype_rules: Vec<QtypeRule>,
+	rebind_protection: Option<RebindConfig>,
+	safe_search: Option<SafeSearch>,
+	/// true if forwarded answers are checked or changed, before they are sent to the client
+	post_process: bool,
 	stats: Stats
 }
 
+/// Result of the filters for a single question.
+enum Filtered {
+	/// the question is answered by the filters
+	Answer(Answer),
+	/// the question has to be forwarded
+	Forward(Requester)
+}
+
+/// Client, which sent a forwarded question.
+struct Requester {
+	ip: IpAddr,
+	group: Option<usize>,
+	/// blocking is paused for the client
+	paused: bool
+}
+
 impl Handler {
-	async fn new(config: &Config, stats: Stats) -> Self {
+	/// Create the handler of `config`.
+	/// `blocklist` must contain the lists of `config`, before the handler is used.
+	async fn new(
+		config: &Config,
+		stats: Stats,
+		blocklist: Arc<BlockList>,
+		pause: Arc<Pause>
+	) -> anyhow::Result<Self> {
 		let zone_name = Name::root();
 		let authority = ForwardAuthority::builder_tokio(config.upstream.clone())
 			.build()
-			.expect("Failed to create forwarder");
+			.map_err(|err| anyhow!("Failed to create forwarder: {err}"))?;
 
 		let mut catalog = Catalog::new();
 		catalog.upsert(zone_name.into(), vec![Arc::new(authority)]);
+		for forward_zone in &config.forward_zones {
+			let mut zone_name = forward_zone.zone.clone();
+			zone_name.set_fqdn(true);
+			let authority =
+				ForwardAuthority::builder_tokio(forward_zone.upstream.clone())
+					.with_origin(zone_name.clone())
+					.build()
+					.map_err(|err| {
+						anyhow!("Failed to create forwarder of zone {zone_name}: {err}")
+					})?;
+			catalog.upsert(zone_name.into(), vec![Arc::new(authority)]);
+		}
+		for zone in &config.zones {
+			match zone.load() {
+				Ok(authority) => {
+					info!(
+						"loaded zone {} with {} records",
+						authority.origin(),
+						authority.records().await.len()
+					);
+					catalog.upsert(authority.origin().clone(), vec![Arc::new(authority)]);
+				},
+				Err(err) => error!("{err}")
+			}
+		}
+		for authority in local::authorities(&config.local_records) {
+			catalog.upsert(authority.origin().clone(), vec![Arc::new(authority)]);
+		}
 
-		let blocklist = BlockList::new();
-		blocklist
-			.update(&config.blocklist.lists, &config.blocklist.allow_list, true)
-			.await;
+		let default_group = default_group(config)?;
+		for group in &config.groups {
+			let configured = config
+				.blocklist
+				.lists
+				.iter()
+				.map(|list| &list.url)
+				.chain(&config.blocklist.hosts)
+				.chain(&config.blocklist.allow_list)
+				.chain(&config.blocklist.ip_blocklist)
+				.chain(&config.blocklist.regex_lists)
+				.chain(&config.blocklist.regex_allow_list)
+				.collect::<Vec<_>>();
+			for url in group
+				.lists
+				.iter()
+				.chain(&group.allow_list)
+				.chain(&group.ip_blocklist)
+				.flatten()
+				.filter(|url| !configured.contains(url))
+			{
+				warn!(
+					"list {url} of group {:?} is not configured at [blocklist]",
+					group.name
+				);
+			}
+		}
 
-		Self {
+		Ok(Self {
 			catalog,
-			blocklist: Arc::new(blocklist),
-			include_subdomains: config.blocklist.include_subdomains,
+			blocklist,
+			block_response: config.blocklist.block_response.clone(),
+			check_cname: config.blocklist.check_cname,
+			groups: config.groups.clone(),
+			default_group,
+			pause,
+			qtype_rules: config.qtype_rules.clone(),
+			rebind_protection: config.rebind_protection.clone(),
+			safe_search: config.safe_search.as_ref().map(|safe_search| {
+				let group_names = config
+					.groups
+					.iter()
+					.map(|group| group.name.as_str())
+					.collect::<Vec<_>>();
+				SafeSearch::new(safe_search, &group_names)
+			}),
+			post_process: config.blocklist.check_cname
+				|| !config.blocklist.ip_blocklist.is_empty()
+				|| config.rebind_protection.is_some()
+				|| config.safe_search.is_some()
+				|| config
+					.qtype_rules
+					.iter()
+					.any(|rule| rule.action == QtypeAction::Strip),
 			stats
+		})
+	}
+}
+
+impl Handler {
+	/// Return the index of the first group, which contains the client `ip`
+	/// and whose schedule is active right now.
+	/// Return the default group, if there is no such group.
+	fn group(&self, ip: IpAddr) -> Option<usize> {
+		self.groups
+			.iter()
+			.position(|group| {
+				group.clients.iter().any(|net| net.contains(&ip))
+					&& group.schedule.as_ref().is_none_or(Schedule::active)
+			})
+			.or(self.default_group)
+	}
+
+	/// Return the names (or urls) of the lists of the client group `group`, which block `name`.
+	/// Return `None` if `name` is not blocked.
+	async fn blocked_by(
+		&self,
+		name: &LowerName,
+		group: Option<usize>
+	) -> Option<Vec<String>> {
+		self.blocklist
+			.blocked(name.to_string().trim_end_matches('.'), group)
+			.await
+	}
+
+	/// Return true if `name` is served by a local authority instead of a forwarder.
+	fn local(&self, name: &LowerName) -> bool {
+		self.catalog.find(name).is_some_and(|authorities| {
+			authorities.iter().any(|f| f.zone_type().is_authoritative())
+		})
+	}
+
+	/// Return true if `name` belongs to a forward zone or a local zone of the config.
+	fn configured_zone(&self, name: &LowerName) -> bool {
+		self.catalog
+			.find(name)
+			.is_some_and(|authorities| authorities.iter().any(|f| !f.origin().is_root()))
+	}
+
+	/// Return the answer of a local authority for `query`.
+	/// CNAME targets are resolved up to a limited depth,
+	/// because the authority of a local name only knows its own records.
+	async fn local_answer(&self, request: &Request, query: &LowerQuery) -> Answer {
+		debug!("local: {query:?}");
+		let mut answer = self.forward(request, query).await;
+		for _ in 0 .. 8 {
+			let Some(target) =
+				answer
+					.answers
+					.last()
+					.and_then(|record| match record.data() {
+						RData::CNAME(cname)
+							if query.query_type() != RecordType::CNAME =>
+						{
+							Some(cname.0.clone())
+						},
+						_ => None
+					})
+			else {
+				break;
+			};
+			let target = LowerQuery::query(Query::query(target, query.query_type()));
+			let target_answer = self.forward(request, &target).await;
+			answer.response_code = target_answer.response_code;
+			answer.authoritative &= target_answer.authoritative;
+			answer.answers.extend(target_answer.answers);
+			answer.name_servers = target_answer.name_servers;
+		}
+		answer
+	}
+
+	/// Return the answer for `query`, if its name is mapped to addresses
+	/// by a hosts file of the client group `group`.
+	async fn hosts(&self, query: &LowerQuery, group: Option<usize>) -> Option<Answer> {
+		if !matches!(query.query_type(), RecordType::A | RecordType::AAAA) {
+			return None;
+		}
+		let ips = self
+			.blocklist
+			.hosts(query.name().to_string().trim_end_matches('.'), group)
+			.await?;
+		debug!("hosts: {query:?}");
+		Some(self.block_response.hosts_answer(query, &ips))
+	}
+
+	/// Return the answer for `query` from the client `ip`,
+	/// if it matches one of the qtype rules.
+	fn qtype_rule(&self, query: &LowerQuery, ip: IpAddr) -> Option<Answer> {
+		let rule = self
+			.qtype_rules
+			.iter()
+			.find(|rule| rule.matches(query.name(), query.query_type(), ip))?;
+		debug!("qtype rule {:?}: {query:?}", rule.action);
+		self.stats
+			.qtype_rule_request
+			.fetch_add(1, Ordering::Relaxed);
+		let name = Name::from(query.name().clone());
+		let answer = match rule.action {
+			QtypeAction::Refuse => Answer::empty(ResponseCode::Refused),
+			QtypeAction::NoData | QtypeAction::Strip => {
+				self.block_response.negative(name, ResponseCode::NoError)
+			},
+			QtypeAction::Block => self.block_response.answer(query)
+		};
+		Some(answer.with_ede(
+			EdeCode::Blocked,
+			&format!("{} queries are filtered", query.query_type())
+		))
+	}
+
+	/// Remove the records of the forwarded `answer` for the client `ip`,
+	/// which are stripped by the qtype rules.
+	fn strip(&self, query: &LowerQuery, mut answer: Answer, ip: IpAddr) -> Answer {
+		let stripped = |record: &Record| {
+			self.qtype_rules
+				.iter()
+				.any(|rule| rule.strips(record, query.name(), ip))
+		};
+		let len = answer.answers.len() + answer.additionals.len();
+		answer.answers.retain(|record| !stripped(record));
+		answer.additionals.retain(|record| !stripped(record));
+		if answer.answers.len() + answer.additionals.len() != len {
+			debug!("qtype rule strip: {query:?}");
+			self.stats
+				.qtype_rule_request
+				.fetch_add(1, Ordering::Relaxed);
 		}
+		answer
+	}
+
+	/// Return the answer for `query`, if it is blocked.
+	async fn blocked(&self, query: &LowerQuery, group: Option<usize>) -> Option<Answer> {
+		let lists = self.blocked_by(query.name(), group).await?;
+		debug!("blocked: {query:?}");
+		self.stats.blocked_request.fetch_add(1, Ordering::Relaxed);
+		Some(self.block_response.answer(query).with_ede(
+			EdeCode::Blocked,
+			&format!("blocked by {}", lists.join(", "))
+		))
+	}
+
+	/// Return the answer for `query` from a client of the group `group`,
+	/// if its name is rewritten to a safe search domain.
+	/// The answer contains a CNAME to the safe search domain
+	/// and the forwarded answer of the safe search domain.
+	async fn safe_search(
+		&self,
+		request: &Request,
+		query: &LowerQuery,
+		group: Option<usize>
+	) -> Option<Answer> {
+		let safe_search = self.safe_search.as_ref()?;
+		let target = safe_search.rewrite(query.name(), group)?;
+		debug!("safe search: {query:?}");
+		let mut answer = Answer::empty(ResponseCode::NoError);
+		answer.answers.push(Record::from_rdata(
+			Name::from(query.name().clone()),
+			safe_search.ttl,
+			RData::CNAME(CNAME(target.clone()))
+		));
+		if query.query_type() != RecordType::CNAME {
+			let target =
+				LowerQuery::query(Query::query(target.clone(), query.query_type()));
+			let target_answer = self.forward(request, &target).await;
+			answer.response_code = target_answer.response_code;
+			answer.truncated = target_answer.truncated;
+			answer.answers.extend(target_answer.answers);
+			answer.name_servers = target_answer.name_servers;
+		}
+		Some(answer)
+	}
+
+	/// Check the forwarded `answer` of `query` and return the block answer,
+	/// if one of its CNAME or DNAME targets or one of its addresses is blocked.
+	async fn check_forwarded(
+		&self,
+		query: &LowerQuery,
+		answer: &Answer,
+		group: Option<usize>
+	) -> Option<Answer> {
+		for ip in answer.answers.iter().filter_map(address) {
+			if let Some(lists) = self.blocklist.ip_blocked(ip, group).await {
+				debug!("blocked: {query:?} by its address {ip}");
+				self.stats.blocked_request.fetch_add(1, Ordering::Relaxed);
+				return Some(self.block_response.answer(query).with_ede(
+					EdeCode::Blocked,
+					&format!("address {ip} blocked by {}", lists.join(", "))
+				));
+			}
+		}
+		if self.check_cname {
+			for target in answer.answers.iter().filter_map(alias_target) {
+				let target = LowerName::new(&target);
+				if let Some(lists) = self.blocked_by(&target, group).await {
+					debug!("blocked: {query:?} by its target {target}");
+					self.stats.blocked_request.fetch_add(1, Ordering::Relaxed);
+					return Some(self.block_response.answer(query).with_ede(
+						EdeCode::Blocked,
+						&format!("target {target} blocked by {}", lists.join(", "))
+					));
+				}
+			}
+		}
+		None
+	}
+
+	/// Remove private, loopback and link-local addresses from the forwarded `answer`
+	/// of `query`, unless its name is allowed to resolve to such addresses.
+	/// Names of the forward zones and local zones are always allowed.
+	/// This protects clients from DNS rebinding attacks.
+	fn rebind_protection(&self, query: &LowerQuery, mut answer: Answer) -> Answer {
+		let Some(config) = &self.rebind_protection else {
+			return answer;
+		};
+		if config.allowed(query.name()) || self.configured_zone(query.name()) {
+			return answer;
+		}
+		let private = |record: &Record| address(record).is_some_and(rebind::is_private);
+		let len = answer.answers.len() + answer.additionals.len();
+		answer.answers.retain(|record| !private(record));
+		answer.additionals.retain(|record| !private(record));
+		if answer.answers.len() + answer.additionals.len() == len {
+			return answer;
+		}
+		debug!("rebind protection: {query:?}");
+		self.stats.rebind_request.fetch_add(1, Ordering::Relaxed);
+		answer.with_ede(EdeCode::Filtered, "private addresses removed")
+	}
+
+	/// Forward a single question of `request` and return the answer.
+	async fn forward(&self, request: &Request, query: &LowerQuery) -> Answer {
+		debug!("{query:?}");
+		let single_request = match single_question_request(request, query) {
+			Ok(single_request) => single_request,
+			Err(err) => {
+				error!("{err:?}");
+				return Answer::empty(ResponseCode::ServFail);
+			}
+		};
+		let capture = Capture::default();
+		self.catalog
+			.handle_request(&single_request, capture.clone())
+			.await;
+		capture
+			.take()
+			.map(Answer::from)
+			.unwrap_or_else(|| Answer::empty(ResponseCode::ServFail))
+	}
+
+	/// Return the answer for a single question of `request`.
+	async fn answer(&self, request: &Request, query: &LowerQuery) -> Answer {
+		match self.filter(request, query).await {
+			Filtered::Answer(answer) => answer,
+			Filtered::Forward(client) => {
+				self.forward_checked(request, query, client).await
+			},
+		}
+	}
+
+	/// Apply the filters to a single question of `request`.
+	async fn filter(&self, request: &Request, query: &LowerQuery) -> Filtered {
+		if self.local(query.name()) {
+			return Filtered::Answer(self.local_answer(request, query).await);
+		}
+		let ip = request.src().ip().to_canonical();
+		let group = self.group(ip);
+		if self
+			.pause
+			.paused(ip, group.map(|group| self.groups[group].name.as_str()))
+		{
+			// only the filtering is paused, local answers are still used
+			debug!("paused: {query:?}");
+			return match self.hosts(query, group).await {
+				Some(answer) => Filtered::Answer(answer),
+				None => Filtered::Forward(Requester {
+					ip,
+					group,
+					paused: true
+				})
+			};
+		}
+		if let Some(answer) = self.qtype_rule(query, ip) {
+			return Filtered::Answer(answer);
+		}
+		if let Some(answer) = self.hosts(query, group).await {
+			return Filtered::Answer(answer);
+		}
+		if let Some(answer) = self.blocked(query, group).await {
+			return Filtered::Answer(answer);
+		}
+		Filtered::Forward(Requester {
+			ip,
+			group,
+			paused: false
+		})
+	}
+
+	/// Forward a single question of `request` from `client`
+	/// and apply the filters to the forwarded answer.
+	async fn forward_checked(
+		&self,
+		request: &Request,
+		query: &LowerQuery,
+		client: Requester
+	) -> Answer {
+		if client.paused {
+			let answer = self.forward(request, query).await;
+			return self.rebind_protection(query, answer);
+		}
+		let answer = match self.safe_search(request, query, client.group).await {
+			Some(answer) => answer,
+			None => self.forward(request, query).await
+		};
+		// the block answer is sent as configured, even if it contains private addresses
+		if let Some(blocked) = self.check_forwarded(query, &answer, client.group).await {
+			return blocked;
+		}
+		let answer = self.rebind_protection(query, answer);
+		self.strip(query, answer, client.ip)
+	}
+
+	/// Answer each question of a message with multiple questions on its own
+	/// and merge the answers into a single response.
+	async fn handle_questions<R: ResponseHandler>(
+		&self,
+		request: &Request,
+		mut response_handler: R
+	) -> ResponseInfo {
+		let queries = request.queries();
+		if queries.is_empty() {
+			debug!("dns query does not contain any question");
+			return response::send(
+				request,
+				&mut response_handler,
+				&Answer::empty(ResponseCode::FormErr)
+			)
+			.await;
+		}
+		let mut answers = Vec::with_capacity(queries.len());
+		for query in queries {
+			self.stats.total_request.fetch_add(1, Ordering::Relaxed);
+			answers.push(self.answer(request, query).await);
+		}
+		let answer = Answer::merge(answers).unwrap_or_else(|| {
+			debug!("questions of a dns query have different response codes");
+			Answer::empty(ResponseCode::FormErr).with_ede(
+				EdeCode::NotSupported,
+				"questions have different response codes, send them in separate queries"
+			)
+		});
+		response::send(request, &mut response_handler, &answer).await
+	}
+}
+
+/// Return the address of an A or AAAA record.
+fn address(record: &Record) -> Option<IpAddr> {
+	match record.data() {
+		RData::A(a) => Some(IpAddr::V4(a.0)),
+		RData::AAAA(aaaa) => Some(IpAddr::V6(aaaa.0)),
+		_ => None
 	}
 }
 
+/// Return the target of a CNAME or DNAME record.
+fn alias_target(record: &Record) -> Option<Name> {
+	match record.data() {
+		RData::CNAME(cname) => Some(cname.0.clone()),
+		RData::Unknown { code, rdata } if *code == RecordType::Unknown(DNAME) => {
+			Name::from_bytes(rdata.anything()).ok()
+		},
+		_ => None
+	}
+}
+
+/// Create a copy of `request`, which contains only `query` as question.
+fn single_question_request(
+	request: &Request,
+	query: &LowerQuery
+) -> anyhow::Result<Request> {
+	let mut message = Message::new();
+	message.set_header(*request.header());
+	message.add_query(query.original().clone());
+	if let Some(edns) = request.edns() {
+		message.set_edns(edns.clone());
+	}
+	let message = MessageRequest::from_bytes(&message.to_vec()?)?;
+	Ok(Request::new(message, request.src(), request.protocol()))
+}
+
 #[async_trait]
 impl RequestHandler for Handler {
 	async fn handle_request<R: ResponseHandler>(
@@ -169,53 +721,81 @@ impl RequestHandler for Handler {
 		request: &Request,
 		mut response_handler: R
 	) -> ResponseInfo {
-		let Ok(lower_query) = request.request_info().map(|v| v.query) else {
-			warn!("Multiple questions in one dns query is currently unsupported");
-			return response_handler
-				.send_response(
-					MessageResponseBuilder::from_message_request(request)
-						.error_msg(request.header(), ResponseCode::ServFail)
-				)
-				.await
-				.unwrap_or_else(|_| {
-					let mut header = Header::new();
-					header.set_response_code(ResponseCode::ServFail);
-					header.into()
-				});
+		let [lower_query] = request.queries() else {
+			return self.handle_questions(request, response_handler).await;
 		};
 		self.stats.total_request.fetch_add(1, Ordering::Relaxed);
-		if self
-			.blocklist
-			.blocked(
-				lower_query.name().to_string().trim_end_matches('.'),
-				self.include_subdomains
-			)
-			.await
-		{
-			debug!("blocked: {lower_query:?}");
-			self.stats.blocked_request.fetch_add(1, Ordering::Relaxed);
-			let mut header = Header::response_from_request(request.header());
-			header.set_response_code(ResponseCode::NXDomain);
-			return response_handler
-				.send_response(
-					MessageResponseBuilder::from_message_request(request).build(
-						header,
-						iter::empty(),
-						iter::empty(),
-						iter::empty(),
-						iter::empty()
-					)
-				)
-				.await
-				.unwrap_or_else(|_| {
-					let mut header = Header::new();
-					header.set_response_code(ResponseCode::ServFail);
-					header.into()
-				});
-		}
+		let answer = match self.filter(request, lower_query).await {
+			Filtered::Answer(answer) => answer,
+			// the response of the upstream is sent unchanged,
+			// so the request does not need to be copied and parsed again
+			Filtered::Forward(_) if !self.post_process => {
+				debug!("{lower_query:?}");
+				return self.catalog.handle_request(request, response_handler).await;
+			},
+			Filtered::Forward(client) => {
+				self.forward_checked(request, lower_query, client).await
+			},
+		};
+		response::send(request, &mut response_handler, &answer).await
+	}
+}
+
+/// Value of the current config, which is replaced if the config is reloaded.
+#[derive(Clone)]
+struct Current<T>(Arc<RwLock<T>>);
+
+impl<T: Clone> Current<T> {
+	fn new(value: T) -> Self {
+		Self(Arc::new(RwLock::new(value)))
+	}
+
+	fn get(&self) -> T {
+		self.0.read().unwrap().clone()
+	}
+
+	fn set(&self, value: T) {
+		*self.0.write().unwrap() = value;
+	}
+}
+
+/// [`Handler`] of a single downstream, which enforces the rate limit of the downstream.
+struct Downstream {
+	handler: Current<Arc<Handler>>,
+	rate_limiter: Current<Option<Arc<RateLimiter>>>
+}
 
-		debug!("{lower_query:?}");
-		self.catalog.handle_request(request, response_handler).await
+#[async_trait]
+impl RequestHandler for Downstream {
+	async fn handle_request<R: ResponseHandler>(
+		&self,
+		request: &Request,
+		mut response_handler: R
+	) -> ResponseInfo {
+		let handler = self.handler.get();
+		let Some(rate_limiter) = self.rate_limiter.get() else {
+			return handler.handle_request(request, response_handler).await;
+		};
+		if rate_limiter.check(request.src().ip()) {
+			return handler.handle_request(request, response_handler).await;
+		}
+		debug!("rate limited: {}", request.src());
+		handler
+			.stats
+			.rate_limited_request
+			.fetch_add(1, Ordering::Relaxed);
+		let mut answer = Answer::empty(ResponseCode::Refused);
+		match rate_limiter.config.action {
+			RateLimitAction::Drop => {
+				return Header::response_from_request(request.header()).into();
+			},
+			RateLimitAction::Truncate if request.protocol() == Protocol::Udp => {
+				answer.response_code = ResponseCode::NoError;
+				answer.truncated = true;
+			},
+			RateLimitAction::Refuse | RateLimitAction::Truncate => {}
+		}
+		response::send(request, &mut response_handler, &answer).await
 	}
 }
 
@@ -258,25 +838,75 @@ fn load_cert_and_key(
 	Ok(Arc::new(SingleCertAndKey::from(certified_key)))
 }
 
+/// Return the path of the file, which stores the `ETag` and `Last-Modified` header
+/// of the cached list `path`.
+fn headers_path(path: &Path) -> PathBuf {
+	let mut headers_path = path.as_os_str().to_owned();
+	headers_path.push(".headers");
+	headers_path.into()
+}
+
+/// Replace the content of `path` with `content`.
+/// The content is written to a temporary file first,
+/// so readers never see a partially written file.
+async fn write_atomic(path: &Path, content: &str) -> anyhow::Result<()> {
+	let mut tmp_path = path.as_os_str().to_owned();
+	tmp_path.push(".tmp");
+	write(&tmp_path, content)
+		.await
+		.with_context(|| format!("failed to save to {tmp_path:?}"))?;
+	rename(&tmp_path, path)
+		.await
+		.with_context(|| format!("failed to rename {tmp_path:?} to {path:?}"))
+}
+
+/// Return the `ETag` and `Last-Modified` header of a response as content of a headers file.
+fn cache_headers(headers: &HeaderMap) -> String {
+	[ETAG, LAST_MODIFIED]
+		.iter()
+		.filter_map(|name| {
+			Some(format!("{name}: {}\n", headers.get(name)?.to_str().ok()?))
+		})
+		.collect()
+}
+
+/// Return the headers of a conditional request for the content `raw` of a headers file.
+fn conditional_headers(raw: &str) -> Vec<(HeaderName, String)> {
+	raw.lines()
+		.filter_map(|line| {
+			let (name, value) = line.split_once(": ")?;
+			let name = match name {
+				"etag" => IF_NONE_MATCH,
+				"last-modified" => IF_MODIFIED_SINCE,
+				_ => return None
+			};
+			Some((name, value.to_owned()))
+		})
+		.collect()
+}
+
 /// Load a text file from url and cache it.
 /// If restore_from_cache is true, only the cache is used.
+/// Otherwise the cached file is only downloaded again, if it was modified.
 /// The first return value is the file content.
 /// It will be None if an error has occured.
 /// The second value is a combined error message.
+/// The third value is true, if the content was loaded from a local file or was downloaded,
+/// instead of being restored from cache.
 async fn get_file(
 	url: &Url,
 	restore_from_cache: bool,
 	cache_file: bool
-) -> (Option<String>, String) {
+) -> (Option<String>, String, bool) {
 	if url.scheme() == "file" {
 		let path = url.path();
 		info!("load file {path:?}");
 		let raw_list = read_to_string(&path).await;
 		match raw_list.with_context(|| format!("can not open file {path:?}")) {
-			Ok(value) => (Some(value), String::new()),
+			Ok(value) => (Some(value), String::new(), true),
 			Err(err) => {
 				error!("{err}");
-				(None, format!("{err}"))
+				(None, format!("{err}"), false)
 			}
 		}
 	} else {
@@ -290,30 +920,52 @@ async fn get_file(
 			path += query;
 		}
 		let path = PathBuf::from(&*LIST_DIR).join(path);
+		let headers_path = headers_path(&path);
 		let raw_list = if !path.exists() || !restore_from_cache {
 			info!("downloading {url}");
-			let resp: anyhow::Result<String> = async {
+			let resp: anyhow::Result<Option<String>> = async {
 				//try block
-				let resp = CLIENT
-					.get(url.to_owned())
-					.send()
-					.await?
-					.error_for_status()?
-					.text()
-					.await?;
+				let mut request = CLIENT.get(url.to_owned());
+				// only ask for a modified version, if the cached version can be used instead
+				if cache_file && path.exists() {
+					let raw_headers =
+						read_to_string(&headers_path).await.unwrap_or_default();
+					for (name, value) in conditional_headers(&raw_headers) {
+						request = request.header(name, value);
+					}
+				}
+				let resp = request.send().await?.error_for_status()?;
+				if resp.status() == StatusCode::NOT_MODIFIED {
+					info!("{url} was not modified");
+					return Ok(None);
+				}
+				let headers = cache_headers(resp.headers());
+				let resp = resp.text().await?;
 				if cache_file {
-					if let Err(err) = write(&path, &resp)
-						.await
-						.with_context(|| format!("failed to save to {path:?}"))
-					{
+					let written = async {
+						// the old version and its headers are kept, if saving the new version fails.
+						// Stale headers of a saved new version only cause a full download.
+						write_atomic(&path, &resp).await?;
+						if headers.is_empty() {
+							match remove_file(&headers_path).await {
+								Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+								_ => Ok(())
+							}
+							.with_context(|| format!("failed to remove {headers_path:?}"))
+						} else {
+							write_atomic(&headers_path, &headers).await
+						}
+					}
+					.await;
+					if let Err(err) = written {
 						error!("{err:?}");
 					}
 				}
-				Ok(resp)
+				Ok(Some(resp))
 			}
 			.await;
 			match resp.with_context(|| format!("error downloading {url}")) {
-				Ok(value) => Some(value),
+				Ok(value) => value,
 				Err(err) => {
 					error!("{err:?}");
 					all_errors += &format!("{err}\n");
@@ -324,146 +976,470 @@ async fn get_file(
 			None
 		};
 		match raw_list {
-			Some(value) => (Some(value), all_errors),
+			Some(value) => (Some(value), all_errors, true),
 			None => {
 				if path.exists() {
 					info!("restore from cache {url}");
-					all_errors += "restore from cache\n";
+					if !all_errors.is_empty() {
+						all_errors += "restore from cache\n";
+					}
 					match read_to_string(&path)
 						.await
 						.with_context(|| format!("error reading file {path:?}"))
 					{
-						Ok(value) => (Some(value), all_errors),
+						Ok(value) => (Some(value), all_errors, false),
 						Err(err) => {
 							error!("{err:?}");
 							all_errors += &format!("{err}\n");
-							(None, all_errors)
+							(None, all_errors, false)
 						}
 					}
 				} else {
-					(None, all_errors)
+					(None, all_errors, false)
 				}
 			},
 		}
 	}
 }
 
-#[tokio::main]
-async fn async_main(config: Config) {
-	let stats = Stats::default();
-	let handler = Handler::new(&config, stats.clone()).await;
-	let blocklist = handler.blocklist.clone();
+/// Start a server for `downstream`, whose queries are answered by `handler`.
+async fn bind(
+	downstream: &DownstreamConfig,
+	handler: Downstream
+) -> anyhow::Result<Server<Downstream>> {
 	let mut server = Server::new(handler);
-	for downstream in config.downstream {
+	match downstream {
+		DownstreamConfig::Udp(downstream) => {
+			let socket_addr = format!("{}:{}", downstream.listen, downstream.port);
+			let udp_socket = UdpSocket::bind(&socket_addr)
+				.await
+				.with_context(|| format!("failed to bind udp socket {socket_addr}"))?;
+			server.register_socket(udp_socket);
+		},
+		DownstreamConfig::Tls(downstream) => {
+			let cert_and_key =
+				load_cert_and_key(&downstream.certificate, &downstream.key)
+					.context("failed to load certificate or private key")?;
+			let socket_addr = format!("{}:{}", downstream.listen, downstream.port);
+			let tcp_listener = TcpListener::bind(&socket_addr)
+				.await
+				.with_context(|| format!("failed to bind tcp socket {socket_addr}"))?;
+			server
+				.register_tls_listener(
+					tcp_listener,
+					Duration::from_millis(downstream.timeout_ms),
+					cert_and_key
+				)
+				.context("failed to register tls downstream")?;
+		},
+		DownstreamConfig::Https(downstream) => {
+			let cert_and_key =
+				load_cert_and_key(&downstream.certificate, &downstream.key)
+					.context("failed to load certificate or private key")?;
+			let socket_addr = format!("{}:{}", downstream.listen, downstream.port);
+			let tcp_listener = TcpListener::bind(&socket_addr)
+				.await
+				.with_context(|| format!("failed to bind tcp socket {socket_addr}"))?;
+			server
+				.register_https_listener(
+					tcp_listener,
+					Duration::from_millis(downstream.timeout_ms),
+					cert_and_key,
+					downstream.dns_hostname.clone(),
+					downstream.http_endpoint.clone()
+				)
+				.context("failed to register https downstream")?;
+		},
+		DownstreamConfig::H3(downstream) => {
+			let cert_and_key =
+				load_cert_and_key(&downstream.certificate, &downstream.key)
+					.context("failed to load certificate or private key")?;
+			let socket_addr = format!("{}:{}", downstream.listen, downstream.port);
+			let udp_socket = UdpSocket::bind(&socket_addr)
+				.await
+				.with_context(|| format!("failed to bind udp socket {socket_addr}"))?;
+			server
+				.register_h3_listener(
+					udp_socket,
+					Duration::from_millis(downstream.timeout_ms),
+					cert_and_key,
+					downstream.dns_hostname.clone()
+				)
+				.context("failed to register h3 downstream")?;
+		},
+		DownstreamConfig::Quic(downstream) => {
+			let cert_and_key =
+				load_cert_and_key(&downstream.certificate, &downstream.key)
+					.context("failed to load certificate or private key")?;
+			let socket_addr = format!("{}:{}", downstream.listen, downstream.port);
+			let udp_socket = UdpSocket::bind(&socket_addr)
+				.await
+				.with_context(|| format!("failed to bind udp socket {socket_addr}"))?;
+			server
+				.register_quic_listener(
+					udp_socket,
+					Duration::from_millis(downstream.timeout_ms),
+					cert_and_key,
+					downstream.dns_hostname.clone()
+				)
+				.context("failed to register quic downstream")?;
+		}
+	}
+	Ok(server)
+}
+
+/// A running downstream.
+struct Listener {
+	config: DownstreamConfig,
+	/// rate limiter of the downstream, which is replaced without restarting the downstream
+	rate_limiter: Current<Option<Arc<RateLimiter>>>,
+	/// requests a graceful shutdown of the server
+	stop: oneshot::Sender<()>,
+	/// task, which runs the server until it stops
+	task: JoinHandle<()>
+}
+
+impl Listener {
+	/// Stop the server gracefully and wait until it is stopped.
+	async fn stop(self) {
+		info!("stop downstream {:?}", self.config);
+		let _ = self.stop.send(());
+		let _ = self.task.await;
+	}
+}
+
+/// The running servers of the current config, which can be reloaded.
+struct Running {
+	config: Config,
+	stats: Stats,
+	blocklist: Arc<BlockList>,
+	pause: Arc<Pause>,
+	handler: Current<Arc<Handler>>,
+	/// rate limiter of the downstreams without their own limit
+	rate_limiter: Option<Arc<RateLimiter>>,
+	listeners: Vec<Listener>,
+	/// receives the errors of servers, which have stopped unexpectedly
+	stopped: UnboundedSender<anyhow::Error>,
+	api: Option<JoinHandle<()>>
+}
+
+/// Return the names of the client groups of `config`.
+fn group_names(config: &Config) -> Vec<String> {
+	config
+		.groups
+		.iter()
+		.map(|group| group.name.clone())
+		.collect()
+}
+
+/// Return the index of the `default_group` of `config` at its client groups.
+fn default_group(config: &Config) -> anyhow::Result<Option<usize>> {
+	config
+		.default_group
+		.as_ref()
+		.map(|name| {
+			config
+				.groups
+				.iter()
+				.position(|group| &group.name == name)
+				.with_context(|| {
+					format!("default_group {name:?} is not configured at [[groups]]")
+				})
+		})
+		.transpose()
+}
+
+impl Running {
+	/// Start the servers of `config`.
+	/// Errors of servers, which stop unexpectedly, are sent to `stopped`.
+	async fn start(config: Config, stopped: UnboundedSender<anyhow::Error>) -> Self {
+		let stats = Stats::default();
+		let blocklist = Arc::new(BlockList::new());
+		blocklist
+			.update(&config.blocklist, &config.groups, true)
+			.await;
+		let pause = Arc::new(Pause::new(group_names(&config)));
+		let handler =
+			Handler::new(&config, stats.clone(), blocklist.clone(), pause.clone())
+				.await
+				.unwrap_or_else(|err| panic!("{err:?}"));
+		// downstreams without their own limit share the global limit
+		let rate_limiter = config
+			.rate_limit
+			.clone()
+			.map(RateLimiter::new)
+			.map(Arc::new);
+		let mut running = Self {
+			config,
+			stats,
+			blocklist,
+			pause,
+			handler: Current::new(Arc::new(handler)),
+			rate_limiter,
+			listeners: Vec::new(),
+			stopped,
+			api: None
+		};
+		for downstream in running.config.downstream.clone() {
+			let listener = running
+				.listen(downstream)
+				.await
+				.unwrap_or_else(|err| panic!("{err:?}"));
+			running.listeners.push(listener);
+		}
+		running.api = Some(running.spawn_api(true));
+		running
+	}
+
+	/// Return the rate limiter used by `downstream`.
+	fn rate_limiter(&self, downstream: &DownstreamConfig) -> Option<Arc<RateLimiter>> {
+		match downstream.rate_limit() {
+			Some(rate_limit) => Some(Arc::new(RateLimiter::new(rate_limit.clone()))),
+			None => self.rate_limiter.clone()
+		}
+	}
+
+	/// Start a listener for `downstream`.
+	async fn listen(&self, downstream: DownstreamConfig) -> anyhow::Result<Listener> {
 		info!("add downstream {:?}", downstream);
-		match downstream {
-			DownstreamConfig::Udp(downstream) => {
-				let socket_addr = format!("{}:{}", downstream.listen, downstream.port);
-				let udp_socket = UdpSocket::bind(&socket_addr)
-					.await
-					.with_context(|| format!("failed to bind udp socket {socket_addr}"))
-					.unwrap_or_else(|err| panic!("{err:?}"));
-				server.register_socket(udp_socket);
-			},
-			DownstreamConfig::Tls(downstream) => {
-				let cert_and_key =
-					load_cert_and_key(&downstream.certificate, &downstream.key)
-						.expect("failed to load certificate or private key");
-				let socket_addr = format!("{}:{}", downstream.listen, downstream.port);
-				let tcp_listener = TcpListener::bind(&socket_addr)
-					.await
-					.with_context(|| format!("failed to bind tcp socket {socket_addr}"))
-					.unwrap_or_else(|err| panic!("{err:?}"));
-				server
-					.register_tls_listener(
-						tcp_listener,
-						Duration::from_millis(downstream.timeout_ms),
-						cert_and_key
-					)
-					.expect("failed to register tls downstream");
-			},
-			DownstreamConfig::Https(downstream) => {
-				let cert_and_key =
-					load_cert_and_key(&downstream.certificate, &downstream.key)
-						.expect("failed to load certificate or private key");
-				let socket_addr = format!("{}:{}", downstream.listen, downstream.port);
-				let tcp_listener =
-					TcpListener::bind(&socket_addr).await.unwrap_or_else(|err| {
-						panic!("failed to bind tcp socket {socket_addr}: {err:?}")
-					});
-				server
-					.register_https_listener(
-						tcp_listener,
-						Duration::from_millis(downstream.timeout_ms),
-						cert_and_key,
-						downstream.dns_hostname,
-						downstream.http_endpoint
-					)
-					.expect("failed to register https downstream");
-			},
-			DownstreamConfig::H3(downstream) => {
-				let cert_and_key =
-					load_cert_and_key(&downstream.certificate, &downstream.key)
-						.expect("failed to load certificate or private key");
-				let socket_addr = format!("{}:{}", downstream.listen, downstream.port);
-				let udp_socket =
-					UdpSocket::bind(&socket_addr).await.unwrap_or_else(|err| {
-						panic!("failed to bind udp socket {socket_addr}: {err:?}")
-					});
-				server
-					.register_h3_listener(
-						udp_socket,
-						Duration::from_millis(downstream.timeout_ms),
-						cert_and_key,
-						downstream.dns_hostname
-					)
-					.expect("failed to register h3 downstream")
-			},
-			DownstreamConfig::Quic(downstream) => {
-				let cert_and_key =
-					load_cert_and_key(&downstream.certificate, &downstream.key)
-						.expect("failed to load certificate or private key");
-				let socket_addr = format!("{}:{}", downstream.listen, downstream.port);
-				let udp_socket =
-					UdpSocket::bind(&socket_addr).await.unwrap_or_else(|err| {
-						panic!("failed to bind udp socket {socket_addr}: {err:?}")
-					});
-				server
-					.register_quic_listener(
-						udp_socket,
-						Duration::from_millis(downstream.timeout_ms),
-						cert_and_key,
-						downstream.dns_hostname
-					)
-					.expect("failed to register quic downstream");
+		let rate_limiter = Current::new(self.rate_limiter(&downstream));
+		let mut server = bind(&downstream, Downstream {
+			handler: self.handler.clone(),
+			rate_limiter: rate_limiter.clone()
+		})
+		.await?;
+		let (stop, stop_requested) = oneshot::channel();
+		let stopped = self.stopped.clone();
+		let name = format!("{downstream:?}");
+		let task = tokio::spawn(async move {
+			tokio::select! {
+				result = server.block_until_done() => {
+					let err = match result {
+						Ok(()) => anyhow!("downstream {name} has stopped"),
+						Err(err) => anyhow!(err).context(format!("downstream {name} has failed"))
+					};
+					let _ = stopped.send(err);
+				},
+				_ = stop_requested => {
+					if let Err(err) = server.shutdown_gracefully().await {
+						error!("failed to stop downstream {name}: {err}");
+					}
+				}
+			}
+		});
+		Ok(Listener {
+			config: downstream,
+			rate_limiter,
+			stop,
+			task
+		})
+	}
+
+	/// Start the api/web server in the background.
+	/// The process is stopped, if the server fails and `exit_on_error` is set.
+	fn spawn_api(&self, exit_on_error: bool) -> JoinHandle<()> {
+		let api = api::init(
+			self.config.api.clone(),
+			self.stats.clone(),
+			self.blocklist.clone(),
+			self.pause.clone()
+		);
+		tokio::spawn(async move {
+			if let Err(err) = api.await.with_context(|| "failed to start api/web server")
+			{
+				error!("{err:?}");
+				if exit_on_error {
+					std::process::exit(1);
+				}
+			}
+		})
+	}
+
+	/// Download the lists, whose refresh interval has expired.
+	/// Return the duration until the next list should be downloaded again.
+	async fn update(&self) -> Duration {
+		self.blocklist
+			.update(&self.config.blocklist, &self.config.groups, false)
+			.await
+	}
+
+	/// Load the config again and apply it, see [`Self::apply`].
+	/// Return the duration until the next list should be downloaded again
+	/// or `None` if the config could not be loaded.
+	async fn reload(&mut self) -> Option<Duration> {
+		let config = match load_config() {
+			Ok(config) => config,
+			Err(err) => {
+				error!("config was not reloaded, the old config is still used: {err:?}");
+				return None;
+			}
+		};
+		self.apply(config).await
+	}
+
+	/// Replace the current config with `config`.
+	/// Downstreams and the api/web server are only restarted, if their config has changed.
+	/// Return the duration until the next list should be downloaded again
+	/// or `None` if the config could not be applied.
+	async fn apply(&mut self, config: Config) -> Option<Duration> {
+		// everything is built before anything is replaced,
+		// so the old config is kept completely, if the new one is invalid
+		let handler = match Handler::new(
+			&config,
+			self.stats.clone(),
+			self.blocklist.clone(),
+			self.pause.clone()
+		)
+		.await
+		{
+			Ok(handler) => handler,
+			Err(err) => {
+				error!("config was not reloaded, the old config is still used: {err:?}");
+				return None;
+			}
+		};
+		let (lists, next_update) = self
+			.blocklist
+			.build(&config.blocklist, &config.groups, false)
+			.await;
+		// the handler selects the lists by the index of the client group,
+		// so the lists, the pauses and the handler are replaced right after each other
+		self.blocklist.replace(lists).await;
+		self.pause.set_groups(group_names(&config));
+		self.handler.set(Arc::new(handler));
+		let old_config = mem::replace(&mut self.config, config);
+
+		let rate_limit_changed = old_config.rate_limit != self.config.rate_limit;
+		if rate_limit_changed {
+			self.rate_limiter = self
+				.config
+				.rate_limit
+				.clone()
+				.map(RateLimiter::new)
+				.map(Arc::new);
+		}
+		let complete = self.update_listeners(rate_limit_changed).await;
+
+		if old_config.api != self.config.api {
+			info!("restart api/web server");
+			if let Some(api) = self.api.take() {
+				api.abort();
+				// wait until the port is free again
+				let _ = api.await;
 			}
+			self.api = Some(self.spawn_api(false));
+		}
+		if complete {
+			info!("🔄 config reloaded");
+		} else {
+			warn!("🔄 config partly reloaded, some downstreams could not be started");
 		}
+		Some(next_update)
 	}
-	let blocklist_move = blocklist.clone();
-	tokio::spawn(async move {
-		let blocklist = blocklist_move;
-		loop {
-			blocklist
-				.update(&config.blocklist.lists, &config.blocklist.allow_list, false)
-				.await;
-			sleep(Duration::from_secs(7200)).await; //2h
+
+	/// Start, restart and stop the listeners, so they match the downstreams of the config.
+	/// Rate limits are replaced without restarting the listener.
+	/// `rate_limit_changed` must be set, if the global rate limit has changed.
+	/// Return false, if a downstream could not be started.
+	/// The old listeners are kept running in this case.
+	async fn update_listeners(&mut self, rate_limit_changed: bool) -> bool {
+		let mut old_listeners = mem::take(&mut self.listeners);
+		let mut new_downstreams = Vec::new();
+		for downstream in self.config.downstream.clone() {
+			let Some(i) = old_listeners.iter().position(|listener| {
+				listener.config.without_rate_limit() == downstream.without_rate_limit()
+			}) else {
+				new_downstreams.push(downstream);
+				continue;
+			};
+			let mut listener = old_listeners.swap_remove(i);
+			if listener.config.rate_limit() != downstream.rate_limit()
+				|| (downstream.rate_limit().is_none() && rate_limit_changed)
+			{
+				info!("change rate limit of downstream {downstream:?}");
+				listener.rate_limiter.set(self.rate_limiter(&downstream));
+			}
+			listener.config = downstream;
+			self.listeners.push(listener);
 		}
-	});
+
+		let mut complete = true;
+		for downstream in new_downstreams {
+			let Some(i) = old_listeners
+				.iter()
+				.position(|listener| listener.config.address() == downstream.address())
+			else {
+				// the new listener is started before the old ones are stopped
+				match self.listen(downstream).await {
+					Ok(listener) => self.listeners.push(listener),
+					Err(err) => {
+						error!("{err:?}");
+						complete = false;
+					}
+				}
+				continue;
+			};
+			// the old listener must be stopped first, to free its address
+			let old_listener = old_listeners.swap_remove(i);
+			let old_downstream = old_listener.config.clone();
+			warn!(
+				"restart downstream {old_downstream:?}, its open connections are closed"
+			);
+			old_listener.stop().await;
+			match self.listen(downstream).await {
+				Ok(listener) => self.listeners.push(listener),
+				Err(err) => {
+					error!("{err:?}");
+					complete = false;
+					warn!("start old downstream {old_downstream:?} again");
+					match self.listen(old_downstream).await {
+						Ok(listener) => self.listeners.push(listener),
+						Err(err) => error!("{err:?}")
+					}
+				}
+			}
+		}
+		if complete {
+			for listener in old_listeners {
+				listener.stop().await;
+			}
+		} else {
+			for listener in old_listeners {
+				warn!("keep old downstream {:?}", listener.config);
+				self.listeners.push(listener);
+			}
+		}
+		complete
+	}
+}
+
+#[tokio::main]
+async fn async_main(config: Config) {
+	let (stopped, mut stopped_servers) = unbounded_channel();
+	let mut running = Running::start(config, stopped).await;
+	let mut reload_trigger =
+		ReloadTrigger::new(CONFIG_PATH.clone(), running.config.watch_config).await;
 	info!("🚀 start dns server");
-	let res = try_join!(
-		async {
-			server
-				.block_until_done()
-				.await
-				.with_context(|| "failed to start dns server")
-		},
-		async {
-			api::init(config.api, stats, blocklist)
-				.await
-				.with_context(|| "failed to start api/web server")
+	let update = sleep(Duration::ZERO);
+	tokio::pin!(update);
+	loop {
+		tokio::select! {
+			_ = &mut update => {
+				let next_update = running.update().await;
+				update.as_mut().reset(Instant::now() + next_update);
+			},
+			_ = reload_trigger.wait() => {
+				if let Some(next_update) = running.reload().await {
+					update.as_mut().reset(Instant::now() + next_update);
+				}
+				reload_trigger.set_watch(running.config.watch_config).await;
+			},
+			Some(err) = stopped_servers.recv() => {
+				error!("{err:?}");
+				std::process::exit(1);
+			}
 		}
-	);
-	res.unwrap();
+	}
 }
 
 #[derive(Debug, Deserialize)]
@@ -473,19 +1449,193 @@ struct Config {
 	downstream: Vec<DownstreamConfig>,
 	#[serde(default)]
 	blocklist: BlockConfig,
-	api: Option<api::Config>
+	api: Option<api::Config>,
+	#[serde(default)]
+	local_records: Vec<LocalRecord>,
+	#[serde(default)]
+	forward_zones: Vec<ForwardZone>,
+	/// authoritative zones loaded from zone files
+	#[serde(default)]
+	zones: Vec<ZoneConfig>,
+	#[serde(default)]
+	groups: Vec<GroupConfig>,
+	/// group of the clients, which are not contained by an active group
+	default_group: Option<String>,
+	/// rules, which filter queries and answers by their record type
+	#[serde(default)]
+	qtype_rules: Vec<QtypeRule>,
+	/// rate limit of each client, used by downstreams without their own rate limit
+	rate_limit: Option<RateLimitConfig>,
+	/// remove private addresses from forwarded answers
+	rebind_protection: Option<RebindConfig>,
+	/// rewrite search engines to their safe search domains
+	safe_search: Option<SafeSearchConfig>,
+	/// reload the config, if the config file is changed
+	#[serde(default)]
+	watch_config: bool
+}
+
+/// group of clients, which uses only a subset of the lists
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GroupConfig {
+	name: String,
+	/// ip ranges of the clients of this group
+	clients: Vec<IpNet>,
+	/// used block lists, hosts files and regex lists. All are used if not set.
+	lists: Option<Vec<Url>>,
+	/// used allow lists and regex allow lists. All are used if not set.
+	allow_list: Option<Vec<Url>>,
+	/// used ip block lists. All are used if not set.
+	ip_blocklist: Option<Vec<Url>>,
+	/// times at which the group is used. Always used if not set.
+	schedule: Option<Schedule>
+}
+
+/// zone, whose queries are sent to its own upstream
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ForwardZone {
+	zone: Name,
+	upstream: ForwardConfig
 }
 
 #[derive(Debug, Default, Deserialize)]
 #[serde(deny_unknown_fields)]
 struct BlockConfig {
-	lists: Vec<Url>,
+	lists: Vec<ListConfig>,
+	/// default of the `include_subdomains` option of the block lists
 	include_subdomains: bool,
 	#[serde(default)]
-	allow_list: Vec<Url>
+	allow_list: Vec<Url>,
+	/// hosts files. Their domains are answered with the mapped addresses,
+	/// domains mapped to unspecified or loopback addresses are blocked.
+	#[serde(default)]
+	hosts: Vec<Url>,
+	/// lists of ip ranges. Forwarded answers containing such an ip are blocked.
+	#[serde(default)]
+	ip_blocklist: Vec<Url>,
+	/// lists of regular expressions. Matching domains are blocked.
+	#[serde(default)]
+	regex_lists: Vec<Url>,
+	/// lists of regular expressions. Matching domains are allowed again.
+	#[serde(default)]
+	regex_allow_list: Vec<Url>,
+	#[serde(default)]
+	block_response: BlockResponse,
+	/// check CNAME and DNAME targets of forwarded answers against the blocklist
+	#[serde(default)]
+	check_cname: bool,
+	/// refresh interval of the lists in seconds, which can be overridden by each block list
+	update_interval: Option<u64>,
+	/// maximal random delay in seconds, which is added to the refresh interval,
+	/// so not all servers download the lists at the same time
+	#[serde(default)]
+	update_jitter: u64,
+	/// maximal count of lists, which are downloaded at the same time
+	parallel_downloads: Option<usize>
 }
 
-#[derive(Debug, Deserialize)]
+/// refresh interval of the lists, if `update_interval` is not set
+const DEFAULT_UPDATE_INTERVAL: u64 = 2 * 60 * 60;
+
+/// count of lists, which are downloaded at the same time, if `parallel_downloads` is not set
+const DEFAULT_PARALLEL_DOWNLOADS: usize = 4;
+
+/// longest refresh interval and random delay of the lists
+const MAX_UPDATE_INTERVAL: u64 = 365 * 24 * 60 * 60;
+
+/// Return the refresh interval of `seconds`.
+/// At least one minute, to avoid updating the lists all the time,
+/// and at most one year, so the time of the next update can not overflow.
+fn update_interval(seconds: u64) -> Duration {
+	Duration::from_secs(seconds.clamp(60, MAX_UPDATE_INTERVAL))
+}
+
+impl BlockConfig {
+	/// refresh interval of the lists without their own interval
+	fn update_interval(&self) -> Duration {
+		update_interval(self.update_interval.unwrap_or(DEFAULT_UPDATE_INTERVAL))
+	}
+
+	fn update_jitter(&self) -> Duration {
+		Duration::from_secs(self.update_jitter.min(MAX_UPDATE_INTERVAL))
+	}
+
+	fn parallel_downloads(&self) -> usize {
+		self.parallel_downloads
+			.unwrap_or(DEFAULT_PARALLEL_DOWNLOADS)
+			.max(1)
+	}
+}
+
+/// A block list of the `lists` config option,
+/// which is either a plain url or a table with additional options.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(from = "RawListConfig")]
+struct ListConfig {
+	url: Url,
+	/// human readable name of the list
+	name: Option<String>,
+	/// disabled lists are not loaded
+	enabled: bool,
+	/// block the subdomains of the listed domains too.
+	/// `BlockConfig::include_subdomains` is used if not set.
+	include_subdomains: Option<bool>,
+	/// the list is downloaded again after this duration
+	interval: Option<Duration>,
+	/// times at which the list blocks domains. Always blocks if not set.
+	schedule: Option<Schedule>
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawListConfig {
+	Url(Url),
+	Table(ListTable)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ListTable {
+	url: Url,
+	name: Option<String>,
+	#[serde(default = "default_enabled")]
+	enabled: bool,
+	include_subdomains: Option<bool>,
+	/// refresh interval in seconds
+	interval: Option<u64>,
+	schedule: Option<Schedule>
+}
+
+fn default_enabled() -> bool {
+	true
+}
+
+impl From<RawListConfig> for ListConfig {
+	fn from(raw: RawListConfig) -> Self {
+		match raw {
+			RawListConfig::Url(url) => Self {
+				url,
+				name: None,
+				enabled: true,
+				include_subdomains: None,
+				interval: None,
+				schedule: None
+			},
+			RawListConfig::Table(table) => Self {
+				url: table.url,
+				name: table.name,
+				enabled: table.enabled,
+				include_subdomains: table.include_subdomains,
+				interval: table.interval.map(update_interval),
+				schedule: table.schedule
+			}
+		}
+	}
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 #[serde(deny_unknown_fields, rename_all = "lowercase", tag = "protocol")]
 enum DownstreamConfig {
 	Udp(UdpConfig),
@@ -495,6 +1645,41 @@ enum DownstreamConfig {
 	Quic(QuicConfig)
 }
 
+impl DownstreamConfig {
+	fn rate_limit(&self) -> Option<&RateLimitConfig> {
+		match self {
+			Self::Udp(config) => config.rate_limit.as_ref(),
+			Self::Tls(config) => config.rate_limit.as_ref(),
+			Self::Https(config) => config.rate_limit.as_ref(),
+			Self::H3(config) | Self::Quic(config) => config.rate_limit.as_ref()
+		}
+	}
+
+	/// Return the config without its rate limit,
+	/// which can be replaced without restarting the downstream.
+	fn without_rate_limit(&self) -> Self {
+		let mut config = self.clone();
+		let rate_limit = match &mut config {
+			Self::Udp(config) => &mut config.rate_limit,
+			Self::Tls(config) => &mut config.rate_limit,
+			Self::Https(config) => &mut config.rate_limit,
+			Self::H3(config) | Self::Quic(config) => &mut config.rate_limit
+		};
+		*rate_limit = None;
+		config
+	}
+
+	/// Return the address and the port, on which the downstream listens.
+	fn address(&self) -> (&str, u16) {
+		match self {
+			Self::Udp(config) => (&config.listen, config.port),
+			Self::Tls(config) => (&config.listen, config.port),
+			Self::Https(config) => (&config.listen, config.port),
+			Self::H3(config) | Self::Quic(config) => (&config.listen, config.port)
+		}
+	}
+}
+
 fn default_timeout() -> u64 {
 	3000
 }
@@ -503,14 +1688,15 @@ fn default_http_endpoint() -> String {
 	"/dns-query".into()
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 #[serde(deny_unknown_fields)]
 struct UdpConfig {
 	port: u16,
-	listen: String
+	listen: String,
+	rate_limit: Option<RateLimitConfig>
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 #[serde(deny_unknown_fields)]
 struct TlsConfig {
 	port: u16,
@@ -518,10 +1704,11 @@ struct TlsConfig {
 	certificate: PathBuf,
 	key: PathBuf,
 	#[serde(default = "default_timeout")]
-	timeout_ms: u64
+	timeout_ms: u64,
+	rate_limit: Option<RateLimitConfig>
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 #[serde(deny_unknown_fields)]
 struct QuicConfig {
 	port: u16,
@@ -530,10 +1717,11 @@ struct QuicConfig {
 	key: PathBuf,
 	#[serde(default = "default_timeout")]
 	timeout_ms: u64,
-	dns_hostname: Option<String>
+	dns_hostname: Option<String>,
+	rate_limit: Option<RateLimitConfig>
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 #[serde(deny_unknown_fields)]
 struct HttpsConfig {
 	port: u16,
@@ -544,7 +1732,8 @@ struct HttpsConfig {
 	timeout_ms: u64,
 	dns_hostname: Option<String>,
 	#[serde(default = "default_http_endpoint")]
-	http_endpoint: String
+	http_endpoint: String,
+	rate_limit: Option<RateLimitConfig>
 }
 
 #[derive(Parser)]
@@ -592,7 +1781,14 @@ fn main() {
 
 	match cli.command {
 		Some(command) => match command {
-			Commands::ValidateConfig => info!("Config is valid"),
+			Commands::ValidateConfig => {
+				if !validate_zones(&config) {
+					error!("Config validation failed!");
+					std::process::exit(1);
+				} else {
+					info!("Config is valid");
+				}
+			},
 			Commands::ValidateLists => {
 				if !async_validate_lists(config) {
 					error!("Config validation failed!");
@@ -609,9 +1805,27 @@ fn main() {
 fn load_config() -> Result<Config, anyhow::Error> {
 	info!("load config from {:?}", &*CONFIG_PATH);
 	let config = fs::read(&*CONFIG_PATH)
-		.with_context(|| format!("Failed to read {:?}", CONFIG_PATH.as_path()))
-		.unwrap_or_else(|err| panic!("{err:?}"));
-	toml::from_slice(&config).with_context(|| "Failed to deserialize config")
+		.with_context(|| format!("Failed to read {:?}", CONFIG_PATH.as_path()))?;
+	parse_config(&config)
+}
+
+/// Deserialize the config `raw` and check the references between its tables.
+fn parse_config(raw: &[u8]) -> Result<Config, anyhow::Error> {
+	let config: Config =
+		toml::from_slice(raw).with_context(|| "Failed to deserialize config")?;
+	default_group(&config).with_context(|| "Invalid config")?;
+	Ok(config)
+}
+
+fn validate_zones(config: &Config) -> bool {
+	let mut validated = true;
+	for zone in &config.zones {
+		if let Err(err) = zone.load() {
+			error!("{err}");
+			validated = false;
+		}
+	}
+	validated
 }
 
 #[tokio::main]
@@ -619,7 +1833,7 @@ async fn async_validate_lists(config: Config) -> bool {
 	let mut validated = true;
 	//Allow List
 	for list in config.blocklist.allow_list {
-		let (file_content, error_message) = get_file(&list, false, false).await;
+		let (file_content, error_message, _) = get_file(&list, false, false).await;
 		if let Some(content) = file_content {
 			if let Err(err) = parser::Blocklist::parse(list.path(), &content) {
 				error!("{}", err.msg());
@@ -632,8 +1846,14 @@ async fn async_validate_lists(config: Config) -> bool {
 	}
 
 	//Block List
-	for list in config.blocklist.lists {
-		let (file_content, error_message) = get_file(&list, false, false).await;
+	for list in config
+		.blocklist
+		.lists
+		.into_iter()
+		.map(|list| list.url)
+		.chain(config.blocklist.hosts)
+	{
+		let (file_content, error_message, _) = get_file(&list, false, false).await;
 		if let Some(content) = file_content {
 			if let Err(err) = parser::Blocklist::parse(list.path(), &content) {
 				error!("{}", err.msg());
@@ -645,24 +1865,450 @@ async fn async_validate_lists(config: Config) -> bool {
 		}
 	}
 
+	//Ip Block List
+	for list in config.blocklist.ip_blocklist {
+		let (file_content, error_message, _) = get_file(&list, false, false).await;
+		if let Some(content) = file_content {
+			if let Err(err) = parser::IpBlocklist::parse(list.path(), &content) {
+				error!("{}", err.msg());
+				validated = false;
+			}
+		} else {
+			error!("{error_message}");
+			validated = false;
+		}
+	}
+
+	//Regex List
+	for list in config
+		.blocklist
+		.regex_lists
+		.into_iter()
+		.chain(config.blocklist.regex_allow_list)
+	{
+		let (file_content, error_message, _) = get_file(&list, false, false).await;
+		if let Some(content) = file_content {
+			if let Err(err) = parser::RegexList::parse(list.path(), &content) {
+				error!("{}", err.msg());
+				validated = false;
+			}
+		} else {
+			error!("{error_message}");
+			validated = false;
+		}
+	}
+
 	validated
 }
 
 #[cfg(test)]
 mod tests {
-	use std::{process::Command, thread, thread::sleep, time::Duration};
+	use hickory_proto::{
+		op::{Message, MessageType, Query},
+		rr::{
+			rdata::{A, CNAME, NULL},
+			Name, RData, Record, RecordType
+		},
+		serialize::binary::{BinDecodable, BinEncodable},
+		xfer::Protocol
+	};
+	use hickory_server::{
+		authority::MessageRequest,
+		server::{Request, RequestHandler as _}
+	};
+	use std::{
+		net::SocketAddr, process::Command, str::FromStr, sync::Arc, thread,
+		thread::sleep, time::Duration
+	};
+	use tokio::{net::UdpSocket, sync::mpsc::unbounded_channel};
 
-	use crate::async_main;
+	use crate::{async_main, pause::Target, response::Capture};
 
 	#[test]
 	fn config_file() {
 		let config = include_bytes!("../config.toml");
-		let _: super::Config = toml::from_slice(config).unwrap();
+		super::parse_config(config).unwrap();
 	}
 	#[test]
 	fn example_config_file() {
 		let config = include_bytes!("../example-config.toml");
-		let _: super::Config = toml::from_slice(config).unwrap();
+		super::parse_config(config).unwrap();
+	}
+
+	#[test]
+	fn list_config() {
+		let config: super::BlockConfig = toml::from_str(indoc::indoc! {r#"
+			include_subdomains = true
+			lists = [
+				"https://example.com/hosts",
+				{ url = "file:///blocked.txt", name = "own", include_subdomains = false, interval = 600 },
+				{ url = "file:///disabled.txt", enabled = false },
+				{ url = "file:///games.txt", schedule = ["sun-thu 20:00-07:00"] }
+			]
+		"#})
+		.unwrap();
+		let [plain, own, disabled, games] = &config.lists[..] else {
+			panic!("unexpected lists {:?}", config.lists);
+		};
+		assert_eq!(plain.url.as_str(), "https://example.com/hosts");
+		assert!(plain.enabled && plain.name.is_none() && plain.interval.is_none());
+		assert_eq!(own.name.as_deref(), Some("own"));
+		assert_eq!(own.include_subdomains, Some(false));
+		assert_eq!(own.interval, Some(Duration::from_secs(600)));
+		assert!(!disabled.enabled);
+		assert!(plain.schedule.is_none() && games.schedule.is_some());
+		let config: super::BlockConfig = toml::from_str(&format!(
+			"update_interval = {0}\nupdate_jitter = {0}\nlists = []\ninclude_subdomains = true",
+			i64::MAX
+		))
+		.unwrap();
+		let max = Duration::from_secs(super::MAX_UPDATE_INTERVAL);
+		assert_eq!(config.update_interval(), max);
+		assert_eq!(config.update_jitter(), max);
+	}
+
+	#[test]
+	fn alias_target() {
+		let name = Name::from_str("example.com.").unwrap();
+		let target = Name::from_str("tracker.example.net.").unwrap();
+		let cname =
+			Record::from_rdata(name.clone(), 60, RData::CNAME(CNAME(target.clone())));
+		assert_eq!(super::alias_target(&cname), Some(target.clone()));
+		let dname = Record::from_rdata(name.clone(), 60, RData::Unknown {
+			code: RecordType::Unknown(super::DNAME),
+			rdata: NULL::with(target.to_bytes().unwrap())
+		});
+		assert_eq!(super::alias_target(&dname), Some(target));
+		let a = Record::from_rdata(name, 60, RData::A(A::new(127, 0, 0, 1)));
+		assert_eq!(super::alias_target(&a), None);
+	}
+
+	#[test]
+	fn conditional_headers() {
+		use reqwest::header::{
+			HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED
+		};
+		let mut headers = HeaderMap::new();
+		headers.insert(ETAG, HeaderValue::from_static("\"5f3a\""));
+		headers.insert(
+			LAST_MODIFIED,
+			HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT")
+		);
+		let raw = super::cache_headers(&headers);
+		assert_eq!(super::conditional_headers(&raw), vec![
+			(IF_NONE_MATCH, "\"5f3a\"".to_owned()),
+			(
+				IF_MODIFIED_SINCE,
+				"Wed, 21 Oct 2015 07:28:00 GMT".to_owned()
+			)
+		]);
+		assert!(super::cache_headers(&HeaderMap::new()).is_empty());
+		assert!(super::conditional_headers("").is_empty());
+	}
+
+	#[tokio::test]
+	async fn write_atomic() {
+		let path = std::env::temp_dir()
+			.join(format!("crab-hole-atomic-{}.txt", std::process::id()));
+		std::fs::write(&path, "old").unwrap();
+		super::write_atomic(&path, "new").await.unwrap();
+		assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+		let mut tmp_path = path.clone().into_os_string();
+		tmp_path.push(".tmp");
+		assert!(!std::path::Path::new(&tmp_path).exists());
+		std::fs::remove_file(&path).unwrap();
+	}
+
+	/// Start an upstream, which answers every query with a private address.
+	/// Names starting with `cname.` are an alias of `tracker.example.net` first.
+	async fn private_upstream() -> SocketAddr {
+		let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+		let addr = socket.local_addr().unwrap();
+		tokio::spawn(async move {
+			let mut buf = [0; 4096];
+			loop {
+				let (len, src) = socket.recv_from(&mut buf).await.unwrap();
+				let request = Message::from_vec(&buf[.. len]).unwrap();
+				let mut response = Message::new();
+				response
+					.set_id(request.id())
+					.set_message_type(MessageType::Response)
+					.set_recursion_desired(true)
+					.set_recursion_available(true)
+					.add_queries(request.queries().to_vec());
+				for query in request.queries() {
+					let mut name = query.name().clone();
+					if name.to_ascii().starts_with("cname.") {
+						let target = Name::from_str("tracker.example.net.").unwrap();
+						response.add_answer(Record::from_rdata(
+							name,
+							60,
+							RData::CNAME(CNAME(target.clone()))
+						));
+						name = target;
+					}
+					response.add_answer(Record::from_rdata(
+						name,
+						60,
+						RData::A(A::new(192, 168, 1, 10))
+					));
+				}
+				socket
+					.send_to(&response.to_vec().unwrap(), src)
+					.await
+					.unwrap();
+			}
+		});
+		addr
+	}
+
+	/// Return the `name_servers` option of an upstream config, which uses `upstream`.
+	fn name_servers(upstream: SocketAddr) -> String {
+		format!(
+			r#"name_servers = [{{ socket_addr = "{upstream}", protocol = "udp", trust_negative_responses = true }}]"#
+		)
+	}
+
+	/// Return the handler of the toml `config` with an empty blocklist.
+	async fn handler(config: &str) -> anyhow::Result<super::Handler> {
+		let config: super::Config = toml::from_str(config).unwrap();
+		super::Handler::new(
+			&config,
+			super::Stats::default(),
+			Arc::new(super::BlockList::new()),
+			Arc::new(super::Pause::new(super::group_names(&config)))
+		)
+		.await
+	}
+
+	/// Return the answer of `handler` for an A query of `name`.
+	async fn answer(handler: &super::Handler, name: &str) -> super::Answer {
+		let mut message = Message::new();
+		message
+			.set_recursion_desired(true)
+			.add_query(Query::query(Name::from_str(name).unwrap(), RecordType::A));
+		let request = MessageRequest::from_bytes(&message.to_vec().unwrap()).unwrap();
+		let request =
+			Request::new(request, "127.0.0.1:53".parse().unwrap(), Protocol::Udp);
+		handler.answer(&request, &request.queries()[0]).await
+	}
+
+	#[tokio::test]
+	async fn rebind_protection_forward_zone() {
+		let name_servers = name_servers(private_upstream().await);
+		let handler = handler(&format!(
+			r#"
+			downstream = []
+			[upstream]
+			{name_servers}
+			[rebind_protection]
+			[[forward_zones]]
+			zone = "lan"
+			[forward_zones.upstream]
+			{name_servers}
+			"#
+		))
+		.await
+		.unwrap();
+		assert_eq!(answer(&handler, "nas.lan.").await.answers.len(), 1);
+		assert!(answer(&handler, "rebind.example.com.")
+			.await
+			.answers
+			.is_empty());
+	}
+
+	#[tokio::test]
+	async fn blocked_target_rebind_protection() {
+		let dir =
+			std::env::temp_dir().join(format!("crab-hole-target-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		std::fs::write(dir.join("block.txt"), "tracker.example.net\n").unwrap();
+		let name_servers = name_servers(private_upstream().await);
+		let handler = handler(&format!(
+			r#"
+			downstream = []
+			[upstream]
+			{name_servers}
+			[rebind_protection]
+			[blocklist]
+			lists = ["file://{}"]
+			include_subdomains = false
+			check_cname = true
+			[blocklist.block_response]
+			mode = "null"
+			"#,
+			dir.join("block.txt").display()
+		))
+		.await
+		.unwrap();
+		let config: super::BlockConfig = toml::from_str(&format!(
+			r#"
+			lists = ["file://{}"]
+			include_subdomains = false
+			"#,
+			dir.join("block.txt").display()
+		))
+		.unwrap();
+		handler.blocklist.update(&config, &[], false).await;
+		std::fs::remove_dir_all(&dir).unwrap();
+
+		// the null answer of a blocked target is not removed by the rebind protection
+		let blocked = answer(&handler, "cname.example.com.").await;
+		assert_eq!(blocked.answers.len(), 1);
+		assert_eq!(blocked.answers[0].data(), &RData::A(A::new(0, 0, 0, 0)));
+		assert_eq!(
+			handler.stats.rebind_request.load(super::Ordering::Relaxed),
+			0
+		);
+		assert!(answer(&handler, "rebind.example.com.")
+			.await
+			.answers
+			.is_empty());
+		assert_eq!(
+			handler.stats.rebind_request.load(super::Ordering::Relaxed),
+			1
+		);
+	}
+
+	#[tokio::test]
+	async fn forward_unchanged() {
+		let name_servers = name_servers(private_upstream().await);
+		let config =
+			|tables| format!("downstream = []\n[upstream]\n{name_servers}\n{tables}");
+		let response = |handler: super::Handler| async move {
+			let mut message = Message::new();
+			message
+				.set_id(7)
+				.set_recursion_desired(true)
+				.add_query(Query::query(
+					Name::from_str("nas.example.com.").unwrap(),
+					RecordType::A
+				));
+			let request = MessageRequest::from_bytes(&message.to_vec().unwrap()).unwrap();
+			let request =
+				Request::new(request, "127.0.0.1:53".parse().unwrap(), Protocol::Udp);
+			let capture = Capture::default();
+			handler.handle_request(&request, capture.clone()).await;
+			capture.take().unwrap()
+		};
+		let direct = handler(&config("")).await.unwrap();
+		assert!(!direct.post_process);
+		let direct = response(direct).await;
+		assert_eq!(direct.id(), 7);
+		assert_eq!(direct.answers().len(), 1);
+		let checked = handler(&config("[rebind_protection]")).await.unwrap();
+		assert!(checked.post_process);
+		assert!(response(checked).await.answers().is_empty());
+	}
+
+	#[tokio::test]
+	async fn default_group() {
+		let config = |default_group: &str| {
+			format!(
+				r#"
+				downstream = []
+				{default_group}
+				[upstream]
+				name_servers = []
+				[[groups]]
+				name = "kids"
+				clients = ["10.0.0.0/8"]
+				[[groups]]
+				name = "adults"
+				clients = []
+				"#
+			)
+		};
+		let kid = "10.0.0.1".parse().unwrap();
+		let other = "192.168.0.1".parse().unwrap();
+		let without_default = handler(&config("")).await.unwrap();
+		assert_eq!(without_default.group(kid), Some(0));
+		assert_eq!(without_default.group(other), None);
+		let with_default = handler(&config(r#"default_group = "adults""#))
+			.await
+			.unwrap();
+		assert_eq!(with_default.group(kid), Some(0));
+		assert_eq!(with_default.group(other), Some(1));
+		assert!(
+			super::parse_config(config(r#"default_group = "adults""#).as_bytes()).is_ok()
+		);
+		assert!(
+			super::parse_config(config(r#"default_group = "unknown""#).as_bytes())
+				.is_err()
+		);
+	}
+
+	/// Return a config with a single udp downstream on `port`, followed by `tables`.
+	fn reload_config(port: u16, tables: &str) -> super::Config {
+		toml::from_str(&format!(
+			r#"
+			[upstream]
+			name_servers = [{{ socket_addr = "127.0.0.1:9", protocol = "udp", trust_negative_responses = true }}]
+			[[downstream]]
+			protocol = "udp"
+			listen = "127.0.0.1"
+			port = {port}
+			{tables}
+			"#
+		))
+		.unwrap()
+	}
+
+	#[tokio::test]
+	async fn apply_config() {
+		let port = std::net::UdpSocket::bind("127.0.0.1:0")
+			.unwrap()
+			.local_addr()
+			.unwrap()
+			.port();
+		let (stopped, mut stopped_servers) = unbounded_channel();
+		let mut running = super::Running::start(reload_config(port, ""), stopped).await;
+		let rate_limiter = running.listeners[0].rate_limiter.clone();
+		assert!(rate_limiter.get().is_none());
+
+		// a changed rate limit does not restart the downstream
+		running
+			.apply(reload_config(
+				port,
+				r#"
+				[rate_limit]
+				queries_per_second = 10
+				[[groups]]
+				name = "kids"
+				clients = ["10.0.0.0/8"]
+				"#
+			))
+			.await
+			.unwrap();
+		assert_eq!(running.listeners.len(), 1);
+		assert!(Arc::ptr_eq(
+			&running.listeners[0].rate_limiter.0,
+			&rate_limiter.0
+		));
+		assert!(rate_limiter.get().is_some());
+		running
+			.pause
+			.pause(Target::Group("kids".to_owned()), time::Duration::minutes(5))
+			.unwrap();
+
+		// the old downstream is kept, if the new one can not be started
+		let used = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+		let used_port = used.local_addr().unwrap().port();
+		running.apply(reload_config(used_port, "")).await.unwrap();
+		assert_eq!(running.listeners.len(), 1);
+		assert_eq!(running.listeners[0].config.address(), ("127.0.0.1", port));
+		assert!(running
+			.pause
+			.pause(Target::Group("kids".to_owned()), time::Duration::minutes(5))
+			.is_err());
+		drop(used);
+		running.apply(reload_config(used_port, "")).await.unwrap();
+		assert_eq!(running.listeners.len(), 1);
+		assert_eq!(
+			running.listeners[0].config.address(),
+			("127.0.0.1", used_port)
+		);
+		assert!(stopped_servers.try_recv().is_err());
 	}
 
 	#[test]
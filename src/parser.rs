@@ -1,6 +1,12 @@
 use ariadne::{Label, Report, ReportKind, Source};
 use chumsky::{error::SimpleReason, prelude::*};
-use std::{fmt::Display, net::IpAddr};
+use hickory_proto::{
+	rr::{Name, RecordSet, RrKey},
+	serialize::txt::Parser as ZoneParser
+};
+use ipnet::IpNet;
+use regex::Regex;
+use std::{collections::BTreeMap, fmt::Display, net::IpAddr, path::Path};
 
 type ParserError = Simple<char>;
 type Span = <ParserError as chumsky::error::Error<char>>::Span;
@@ -17,10 +23,11 @@ pub(crate) struct Domain(pub(crate) String);
 
 impl Domain {
 	fn parser() -> impl Parser<char, Self, Error = ParserError> {
-		let ident =
-			filter(|c: &char| *c != '#' && *c != ':' && *c != '.' && !c.is_whitespace())
-				.repeated()
-				.at_least(1);
+		let ident = filter(|c: &char| {
+			!matches!(c, '#' | ':' | '.' | '^' | '|') && !c.is_whitespace()
+		})
+		.repeated()
+		.at_least(1);
 		ident
 			.then(just(".").then(ident).repeated())
 			.then_ignore(just(".").ignored().or(empty()))
@@ -28,7 +35,7 @@ impl Domain {
 				let mut domain: String = first.into_iter().collect();
 				for (punct, part) in tail {
 					domain += punct;
-					domain.extend(part.into_iter());
+					domain.extend(part);
 				}
 				Self(domain)
 			})
@@ -111,40 +118,226 @@ impl ParseError<'_> {
 
 pub(crate) type ParseResult<'a, T> = Result<T, ParseError<'a>>;
 
+/// Parse `input` with `parser` and collect all errors.
+fn parse<'a, T>(
+	parser: impl Parser<char, T, Error = ParserError>,
+	path: &'a str,
+	input: &'a str
+) -> ParseResult<'a, T> {
+	#[cfg(feature = "__debug_parser")]
+	let result = parser.parse_recovery_verbose(input);
+	#[cfg(not(feature = "__debug_parser"))]
+	let result = parser.parse_recovery(input);
+	match result {
+		(Some(value), errs) if errs.is_empty() => Ok(value),
+		(_, errs) => Err(ParseError {
+			input,
+			path_str: path,
+			err: errs
+		})
+	}
+}
+
+/// Parser of a file, where each line is parsed by the parser created by `line`.
+/// Lines without a value (like comments) are skipped.
+fn lines<T, P>(line: impl Fn() -> P) -> impl Parser<char, Vec<T>, Error = ParserError>
+where
+	P: Parser<char, Option<T>, Error = ParserError>
+{
+	line()
+		.then_ignore(one_of(['\r', '\n']).repeated().at_least(1))
+		.repeated()
+		.then(line())
+		.then_ignore(one_of(['\r', '\n']).repeated())
+		.then_ignore(end())
+		.map(|(mut entries, last)| {
+			entries.push(last);
+			entries.into_iter().flatten().collect()
+		})
+}
+
 impl Blocklist {
 	pub(crate) fn parse<'a>(path: &'a str, input: &'a str) -> ParseResult<'a, Self> {
-		let parser = Self::parser();
-		#[cfg(feature = "__debug_parser")]
-		let result = parser.parse_recovery_verbose(input);
-		#[cfg(not(feature = "__debug_parser"))]
-		let result = parser.parse_recovery(input);
-		match result {
-			(Some(value), errs) if errs.is_empty() => Ok(value),
-			(_, errs) => Err(ParseError {
-				input,
-				path_str: path,
-				err: errs
-			})
-		}
+		parse(Self::parser(), path, input)
 	}
 
 	fn parser() -> impl Parser<char, Self, Error = ParserError> {
-		Line::parser()
-			.then_ignore(one_of(['\r', '\n']).repeated().at_least(1))
-			.repeated()
-			.then(Line::parser())
-			.then_ignore(one_of(['\r', '\n']).repeated())
-			.then_ignore(end())
-			.map(|(mut entries, last)| {
-				entries.push(last);
-				Self {
-					entries: entries.into_iter().flatten().collect()
-				}
-			})
+		lines(Line::parser)
+			.map(|entries| Self { entries })
 			.debug("Blocklist parser")
 	}
 }
 
+/// A list of ip ranges, one range in CIDR notation or a single ip per line.
+pub(crate) struct IpBlocklist {
+	pub(crate) entries: Vec<IpNet>
+}
+
+impl IpBlocklist {
+	pub(crate) fn parse<'a>(path: &'a str, input: &'a str) -> ParseResult<'a, Self> {
+		parse(Self::parser(), path, input)
+	}
+
+	fn parser() -> impl Parser<char, Self, Error = ParserError> {
+		lines(Self::line_parser)
+			.map(|entries| Self { entries })
+			.debug("IpBlocklist parser")
+	}
+
+	fn line_parser() -> impl Parser<char, Option<IpNet>, Error = ParserError> {
+		let net = filter(|c: &char| {
+			c.is_ascii_hexdigit() || *c == '.' || *c == ':' || *c == '/'
+		})
+		.repeated()
+		.at_least(2)
+		.collect::<String>()
+		.try_map(|net, span| {
+			if net.contains('/') {
+				net.parse::<IpNet>()
+					.map(|net| net.trunc())
+					.map_err(|err| convert_error(err, span))
+			} else {
+				net.parse::<IpAddr>()
+					.map(IpNet::from)
+					.map_err(|err| convert_error(err, span))
+			}
+		});
+		choice((
+			// <ip>[/<prefix len>]
+			one_of([' ', '\t'])
+				.repeated()
+				.ignore_then(net)
+				.then_ignore(one_of([' ', '\t']).repeated())
+				.then_ignore(choice((Comment::parser().ignored(), empty())))
+				.map(Some)
+				.debug("IpLine parser: Net"),
+			// full line comment
+			Comment::parser()
+				.map(|_| None)
+				.debug("IpLine parser: Comment"),
+			// empty line
+			one_of([' ', '\t'])
+				.repeated()
+				.map(|_| None)
+				.debug("IpLine parser: Empty")
+		))
+		.debug("IpLine parser")
+	}
+}
+
+/// A list of regular expressions, which are matched against domains.
+/// One expression per line, lines starting with `#` are comments.
+#[derive(Debug)]
+pub(crate) struct RegexList {
+	pub(crate) entries: Vec<String>
+}
+
+impl RegexList {
+	pub(crate) fn parse<'a>(path: &'a str, input: &'a str) -> ParseResult<'a, Self> {
+		parse(Self::parser(), path, input)
+	}
+
+	fn parser() -> impl Parser<char, Self, Error = ParserError> {
+		lines(Self::line_parser)
+			.map(|entries| Self { entries })
+			.debug("RegexList parser")
+	}
+
+	fn line_parser() -> impl Parser<char, Option<String>, Error = ParserError> {
+		choice((
+			// full line comment
+			Comment::parser()
+				.map(|_| None)
+				.debug("RegexLine parser: Comment"),
+			// regex or empty line
+			none_of(['\r', '\n'])
+				.repeated()
+				.collect::<String>()
+				.try_map(|line, span| {
+					let regex = line.trim();
+					if regex.is_empty() {
+						return Ok(None);
+					}
+					Regex::new(regex)
+						.map(|_| Some(regex.to_owned()))
+						.map_err(|err| convert_error(err, span))
+				})
+				.debug("RegexLine parser: Regex")
+		))
+		.debug("RegexLine parser")
+	}
+}
+
+/// A zone file (RFC 1035 master file).
+#[derive(Debug)]
+pub(crate) struct Zone {
+	pub(crate) origin: Name,
+	pub(crate) records: BTreeMap<RrKey, RecordSet>
+}
+
+/// Return the entries of a zone file as pairs of
+/// the byte offset after the entry and the char span of the entry.
+/// An entry is a line, or multiple lines if it contains parentheses.
+fn zone_entries(input: &str) -> Vec<(usize, Span)> {
+	let mut entries = Vec::new();
+	let (mut depth, mut quoted, mut comment) = (0_usize, false, false);
+	let mut escaped = false;
+	let mut start = 0;
+	for (char_index, (byte_index, c)) in input.char_indices().enumerate() {
+		match c {
+			_ if escaped => escaped = false,
+			'\\' if quoted => escaped = true,
+			'"' if !comment => quoted = !quoted,
+			';' if !quoted => comment = true,
+			'(' if !quoted && !comment => depth += 1,
+			')' if !quoted && !comment => depth = depth.saturating_sub(1),
+			'\n' => {
+				comment = false;
+				if depth == 0 {
+					entries.push((byte_index + 1, start .. char_index));
+					start = char_index + 1;
+				}
+			},
+			_ => {}
+		}
+	}
+	entries.push((input.len(), start .. input.chars().count()));
+	entries
+}
+
+impl Zone {
+	/// Parse the zone file `input` of the zone `origin`.
+	/// `path` is used to resolve relative `$INCLUDE` paths.
+	pub(crate) fn parse<'a>(
+		path_str: &'a str,
+		path: &Path,
+		input: &'a str,
+		origin: Name
+	) -> ParseResult<'a, Self> {
+		let parse = |input: &str| {
+			ZoneParser::new(input, Some(path.to_owned()), Some(origin.clone())).parse()
+		};
+		let err = match parse(input) {
+			Ok((origin, records)) => return Ok(Self { origin, records }),
+			Err(err) => err
+		};
+		// hickory does not report the position of an error,
+		// so search the first entry, which can not be parsed together with the entries before it.
+		let entries = zone_entries(input);
+		let failed = entries.partition_point(|(end, _)| parse(&input[.. *end]).is_ok());
+		let span = entries
+			.get(failed)
+			.or(entries.last())
+			.map(|(_, span)| span.clone())
+			.unwrap_or_default();
+		Err(ParseError {
+			input,
+			path_str,
+			err: vec![convert_error(err, span)]
+		})
+	}
+}
+
 struct Comment;
 
 impl Comment {
@@ -162,7 +355,9 @@ impl Comment {
 pub(crate) enum Line {
 	Domain(Domain),
 	IpDomain(IpAddr, Domain),
-	IpIfaceDomain(IpAddr, String, Domain)
+	IpIfaceDomain(IpAddr, String, Domain),
+	/// `*.<domain>` or `||<domain>^`, which matches the domain and all of its subdomains
+	Wildcard(Domain)
 }
 
 impl Line {
@@ -170,12 +365,29 @@ impl Line {
 		match self {
 			Self::Domain(domain)
 			| Self::IpDomain(_, domain)
-			| Self::IpIfaceDomain(_, _, domain) => domain
+			| Self::IpIfaceDomain(_, _, domain)
+			| Self::Wildcard(domain) => domain
 		}
 	}
 
+	/// Return true if the entry matches the subdomains of its domain too.
+	pub(crate) fn include_subdomains(&self) -> bool {
+		matches!(self, Self::Wildcard(_))
+	}
+
 	fn parser() -> impl Parser<char, Option<Self>, Error = ParserError> {
 		choice((
+			// *.<domain> or ||<domain>^
+			choice((
+				just("*.").ignore_then(Domain::parser()),
+				just("||")
+					.ignore_then(Domain::parser())
+					.then_ignore(just("^"))
+			))
+			.map(|domain| Some(Self::Wildcard(domain)))
+			.then_ignore(one_of([' ', '\t']).repeated())
+			.then_ignore(choice((Comment::parser().ignored(), empty())))
+			.debug("Line parser: Wildcard"),
 			// [<ip>][%<iface>] <domain>
 			choice((
 				filter(|c: &char| c.is_ascii_hexdigit() || *c == '.' || *c == ':')
@@ -327,6 +539,31 @@ mod tests {
 		]);
 	}
 
+	#[test]
+	fn wildcard_domain() {
+		let input = indoc! {"
+		*.example.com
+		||foo.baaa.dev^ # adblock syntax
+		example.org
+		"};
+		let blocklist = parse(input);
+		let entries: Vec<(String, bool)> = blocklist
+			.entries
+			.iter()
+			.map(|f| (f.domain().0.clone(), f.include_subdomains()))
+			.collect();
+		assert_eq!(entries, vec![
+			("example.com".into(), true),
+			("foo.baaa.dev".into(), true),
+			("example.org".into(), false),
+		]);
+	}
+
+	#[test]
+	fn wildcard_domain_unclosed() {
+		assert!(Blocklist::parse("<test-input>", "||example.com\n").is_err());
+	}
+
 	#[test]
 	fn comment() {
 		test("#example.com\n", vec![]);
@@ -369,4 +606,107 @@ mod tests {
 	fn loopback_ipv6_domain() {
 		test("fe80::1%lo0 localhost", vec!["localhost".into()]);
 	}
+
+	fn test_ip(input: &str, output: Vec<&str>) {
+		let list = match IpBlocklist::parse("<test-input>", input) {
+			Ok(list) => list,
+			Err(err) => {
+				panic!("Failed to parse input\n{}", err.msg());
+			}
+		};
+		let output: Vec<IpNet> = output.into_iter().map(|f| f.parse().unwrap()).collect();
+		assert_eq!(list.entries, output);
+	}
+
+	#[test]
+	fn ip_list() {
+		let input = indoc! {"
+		# bad networks
+		192.0.2.0/24
+		198.51.100.7 # single host
+
+		2001:db8::/32
+		"};
+		test_ip(input, vec![
+			"192.0.2.0/24",
+			"198.51.100.7/32",
+			"2001:db8::/32",
+		]);
+	}
+
+	#[test]
+	fn ip_list_host_bits() {
+		test_ip("10.1.2.3/8", vec!["10.0.0.0/8"]);
+	}
+
+	#[test]
+	fn ip_list_invalid() {
+		assert!(IpBlocklist::parse("<test-input>", "example.com\n").is_err());
+		assert!(IpBlocklist::parse("<test-input>", "10.0.0.0/33\n").is_err());
+	}
+
+	#[test]
+	fn regex_list() {
+		let input = indoc! {r"
+		# ad servers
+		^ad[s]?[0-9]*\.
+
+		(^|\.)track(ing)?\.
+		"};
+		let list = match RegexList::parse("<test-input>", input) {
+			Ok(list) => list,
+			Err(err) => panic!("Failed to parse input\n{}", err.msg())
+		};
+		assert_eq!(list.entries, vec![
+			r"^ad[s]?[0-9]*\.",
+			r"(^|\.)track(ing)?\."
+		]);
+	}
+
+	#[test]
+	fn regex_list_invalid() {
+		let err = RegexList::parse("<test-input>", "^ads\\.\n(^|\\.)track(\n")
+			.unwrap_err()
+			.msg();
+		assert!(err.contains("<test-input>:2:1"), "{err}");
+	}
+
+	fn zone(input: &str) -> Result<Zone, String> {
+		Zone::parse(
+			"<test-input>",
+			Path::new("/"),
+			input,
+			Name::from_ascii("home.arpa.").unwrap()
+		)
+		.map_err(|err| err.msg())
+	}
+
+	#[test]
+	fn zone_file() {
+		let zone = zone(indoc! {r#"
+			$TTL 3600
+			@ IN SOA ns.home.arpa. hostmaster.home.arpa. (
+				1 ; serial
+				7200 3600 1209600 3600 )
+			nas IN A 192.168.1.10
+			txt IN TXT "hello ; world"
+		"#})
+		.unwrap();
+		assert_eq!(zone.origin, Name::from_ascii("home.arpa.").unwrap());
+		assert_eq!(zone.records.len(), 3);
+	}
+
+	#[test]
+	fn zone_file_error_line() {
+		let err = zone(indoc! {r#"
+			$TTL 3600
+			@ IN SOA ns.home.arpa. hostmaster.home.arpa. (
+				1 7200 3600 1209600 3600 )
+			nas IN A 192.168.1.10
+			printer IN A 192.168.1.300
+			tv IN A 192.168.1.12
+		"#})
+		.unwrap_err();
+		assert!(err.contains("<test-input>:5:1"), "{err}");
+	}
 }
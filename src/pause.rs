@@ -0,0 +1,184 @@
+use anyhow::{bail, Context};
+use poem_openapi::Object;
+use std::{collections::HashMap, net::IpAddr, sync::Mutex};
+use time::{Duration, OffsetDateTime};
+
+/// Clients, for which blocking can be paused.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Target {
+	All,
+	Client(IpAddr),
+	/// name of a client group
+	Group(String)
+}
+
+/// Pauses of blocking, which end at their expiry.
+#[derive(Debug, Default)]
+pub(crate) struct Pause {
+	group_names: Mutex<Vec<String>>,
+	expiry: Mutex<HashMap<Target, OffsetDateTime>>
+}
+
+#[derive(Debug, Object)]
+pub(crate) struct PausedClient {
+	client: String,
+	until: OffsetDateTime
+}
+
+#[derive(Debug, Object)]
+pub(crate) struct PausedGroup {
+	group: String,
+	until: OffsetDateTime
+}
+
+/// Running pauses of blocking.
+#[derive(Debug, Default, Object)]
+pub(crate) struct PauseState {
+	/// end of the pause of all clients
+	all: Option<OffsetDateTime>,
+	clients: Vec<PausedClient>,
+	groups: Vec<PausedGroup>
+}
+
+impl Pause {
+	pub(crate) fn new(group_names: Vec<String>) -> Self {
+		Self {
+			group_names: Mutex::new(group_names),
+			..Default::default()
+		}
+	}
+
+	/// Replace the names of the client groups, after the config was reloaded.
+	/// Pauses of removed groups are ended.
+	pub(crate) fn set_groups(&self, group_names: Vec<String>) {
+		self.expiry
+			.lock()
+			.unwrap()
+			.retain(|target, _| match target {
+				Target::Group(group) => group_names.contains(group),
+				Target::All | Target::Client(_) => true
+			});
+		*self.group_names.lock().unwrap() = group_names;
+	}
+
+	/// Pause blocking for `target` during `duration`.
+	/// A running pause of `target` is replaced.
+	/// Fail if the end of the pause is out of the supported time range.
+	pub(crate) fn pause(&self, target: Target, duration: Duration) -> anyhow::Result<()> {
+		if let Target::Group(group) = &target {
+			if !self.group_names.lock().unwrap().contains(group) {
+				bail!("unknown group {group:?}");
+			}
+		}
+		let until = OffsetDateTime::now_utc()
+			.checked_add(duration)
+			.context("pause is too long")?;
+		self.expiry.lock().unwrap().insert(target, until);
+		Ok(())
+	}
+
+	/// End the pause of `target` early.
+	pub(crate) fn resume(&self, target: &Target) {
+		self.expiry.lock().unwrap().remove(target);
+	}
+
+	/// Return true if blocking is paused for the client `ip` of the group `group`
+	/// and forget the expired pauses.
+	pub(crate) fn paused(&self, ip: IpAddr, group: Option<&str>) -> bool {
+		let mut expiry = self.expiry.lock().unwrap();
+		if expiry.is_empty() {
+			return false;
+		}
+		let now = OffsetDateTime::now_utc();
+		expiry.retain(|_, until| *until > now);
+		expiry.keys().any(|target| match target {
+			Target::All => true,
+			Target::Client(client) => *client == ip,
+			Target::Group(name) => group == Some(name.as_str())
+		})
+	}
+
+	/// Return the running pauses and forget the expired ones.
+	pub(crate) fn state(&self) -> PauseState {
+		let mut expiry = self.expiry.lock().unwrap();
+		let now = OffsetDateTime::now_utc();
+		expiry.retain(|_, until| *until > now);
+		let mut state = PauseState::default();
+		for (target, until) in expiry.iter() {
+			let until = *until;
+			match target {
+				Target::All => state.all = Some(until),
+				Target::Client(client) => state.clients.push(PausedClient {
+					client: client.to_string(),
+					until
+				}),
+				Target::Group(group) => state.groups.push(PausedGroup {
+					group: group.clone(),
+					until
+				})
+			}
+		}
+		state.clients.sort_by(|a, b| a.client.cmp(&b.client));
+		state.groups.sort_by(|a, b| a.group.cmp(&b.group));
+		state
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn pause() {
+		let pause = Pause::new(vec!["kids".to_owned()]);
+		let ip: IpAddr = "192.168.1.2".parse().unwrap();
+		let other: IpAddr = "192.168.1.3".parse().unwrap();
+		assert!(!pause.paused(ip, Some("kids")));
+		pause
+			.pause(Target::Group("kids".to_owned()), Duration::minutes(5))
+			.unwrap();
+		assert!(pause.paused(ip, Some("kids")));
+		assert!(!pause.paused(ip, None));
+		pause
+			.pause(Target::Client(other), Duration::minutes(5))
+			.unwrap();
+		assert!(pause.paused(other, None));
+		pause.resume(&Target::Client(other));
+		assert!(!pause.paused(other, None));
+		assert!(pause
+			.pause(Target::Group("office".to_owned()), Duration::minutes(5))
+			.is_err());
+		assert!(pause
+			.pause(Target::All, Duration::minutes(u32::MAX.into()))
+			.is_err());
+		pause.pause(Target::All, Duration::ZERO).unwrap();
+		assert!(!pause.paused(other, None));
+		// expired pauses are forgotten
+		assert_eq!(pause.expiry.lock().unwrap().len(), 1);
+		let state = pause.state();
+		assert!(state.all.is_none() && state.clients.is_empty());
+		assert_eq!(state.groups.len(), 1);
+	}
+
+	#[test]
+	fn set_groups() {
+		let pause = Pause::new(vec!["kids".to_owned(), "guests".to_owned()]);
+		for group in ["kids", "guests"] {
+			pause
+				.pause(Target::Group(group.to_owned()), Duration::minutes(5))
+				.unwrap();
+		}
+		pause.pause(Target::All, Duration::minutes(5)).unwrap();
+		pause.set_groups(vec!["guests".to_owned(), "office".to_owned()]);
+		assert!(pause
+			.pause(Target::Group("kids".to_owned()), Duration::minutes(5))
+			.is_err());
+		pause
+			.pause(Target::Group("office".to_owned()), Duration::minutes(5))
+			.unwrap();
+		let state = pause.state();
+		assert!(state.all.is_some());
+		let groups: Vec<_> = state.groups.iter().map(|paused| &paused.group).collect();
+		assert_eq!(groups, ["guests", "office"]);
+	}
+}
@@ -0,0 +1,113 @@
+use hickory_proto::rr::{LowerName, Name, Record, RecordType};
+use ipnet::IpNet;
+use serde::Deserialize;
+use std::net::IpAddr;
+
+/// What happens with queries, which match a [`QtypeRule`].
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum QtypeAction {
+	/// answer with REFUSED
+	Refuse,
+	/// answer without records
+	NoData,
+	/// answer like a blocked domain, see [`BlockResponse`](crate::response::BlockResponse)
+	Block,
+	/// answer without records and remove records of the types from other answers
+	Strip
+}
+
+/// A rule of the `[[qtype_rules]]` config section.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct QtypeRule {
+	/// record types, which are matched by this rule
+	types: Vec<RecordType>,
+	/// domains (including their subdomains) matched by this rule. All domains are matched if not set.
+	domains: Option<Vec<Name>>,
+	/// domains (including their subdomains), which are not matched by this rule
+	#[serde(default)]
+	except: Vec<Name>,
+	/// ip ranges of clients matched by this rule. All clients are matched if not set.
+	clients: Option<Vec<IpNet>>,
+	pub(crate) action: QtypeAction
+}
+
+impl QtypeRule {
+	/// Return true if the rule applies to queries of `name` from the client `ip`.
+	fn applies(&self, name: &LowerName, ip: IpAddr) -> bool {
+		let zone_of = |domain: &Name| LowerName::new(domain).zone_of(name);
+		self.domains
+			.as_ref()
+			.is_none_or(|domains| domains.iter().any(zone_of))
+			&& !self.except.iter().any(zone_of)
+			&& self
+				.clients
+				.as_ref()
+				.is_none_or(|clients| clients.iter().any(|net| net.contains(&ip)))
+	}
+
+	/// Return true if the rule matches a query of `query_type` for `name` from the client `ip`.
+	pub(crate) fn matches(
+		&self,
+		name: &LowerName,
+		query_type: RecordType,
+		ip: IpAddr
+	) -> bool {
+		self.types.contains(&query_type) && self.applies(name, ip)
+	}
+
+	/// Return true if the rule removes `record` from an answer for `name` to the client `ip`.
+	pub(crate) fn strips(&self, record: &Record, name: &LowerName, ip: IpAddr) -> bool {
+		self.action == QtypeAction::Strip
+			&& self.types.contains(&record.record_type())
+			&& self.applies(name, ip)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use hickory_proto::rr::{rdata::AAAA, RData};
+	use std::{net::Ipv6Addr, str::FromStr};
+
+	fn name(name: &str) -> LowerName {
+		LowerName::from_str(name).unwrap()
+	}
+
+	#[test]
+	fn domains() {
+		let rule: QtypeRule = toml::from_str(indoc::indoc! {r#"
+			types = ["HTTPS", "SVCB"]
+			domains = ["example.com"]
+			except = ["allowed.example.com"]
+			action = "nodata"
+		"#})
+		.unwrap();
+		let client = "192.168.1.2".parse().unwrap();
+		assert!(rule.matches(&name("example.com."), RecordType::HTTPS, client));
+		assert!(rule.matches(&name("www.Example.com."), RecordType::SVCB, client));
+		assert!(!rule.matches(&name("www.example.com."), RecordType::A, client));
+		assert!(!rule.matches(&name("example.net."), RecordType::HTTPS, client));
+		assert!(!rule.matches(&name("allowed.example.com."), RecordType::HTTPS, client));
+	}
+
+	#[test]
+	fn strip() {
+		let rule: QtypeRule = toml::from_str(indoc::indoc! {r#"
+			types = ["AAAA"]
+			clients = ["192.168.20.0/24"]
+			action = "strip"
+		"#})
+		.unwrap();
+		let record = Record::from_rdata(
+			Name::from_str("example.com.").unwrap(),
+			60,
+			RData::AAAA(AAAA(Ipv6Addr::LOCALHOST))
+		);
+		let name = name("example.com.");
+		assert!(rule.strips(&record, &name, "192.168.20.5".parse().unwrap()));
+		assert!(!rule.strips(&record, &name, "192.168.1.5".parse().unwrap()));
+		assert!(rule.matches(&name, RecordType::AAAA, "192.168.20.5".parse().unwrap()));
+	}
+}
@@ -0,0 +1,234 @@
+use ipnet::IpNet;
+use serde::{de::Error as _, Deserialize, Deserializer};
+use std::{
+	collections::HashMap,
+	net::IpAddr,
+	sync::Mutex,
+	time::{Duration, Instant}
+};
+
+/// How often buckets of clients, which did not send queries for a while, are removed.
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What happens with queries of clients, which are over their limit.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum RateLimitAction {
+	/// answer with REFUSED
+	#[default]
+	Refuse,
+	/// do not answer
+	Drop,
+	/// answer with an empty truncated answer, so the client retries over tcp.
+	/// Queries, which were not received over udp, are refused.
+	Truncate
+}
+
+/// Limits of the queries of each client.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RateLimitConfig {
+	/// allowed queries per second
+	#[serde(deserialize_with = "positive")]
+	queries_per_second: f64,
+	/// count of queries, which can be sent at once (default = `queries_per_second`, at least 1)
+	#[serde(default, deserialize_with = "burst")]
+	burst: Option<f64>,
+	/// clients inside the same ipv4 prefix share their limit
+	#[serde(default = "default_ipv4_prefix", deserialize_with = "prefix::<_, 32>")]
+	ipv4_prefix: u8,
+	/// clients inside the same ipv6 prefix share their limit
+	#[serde(default = "default_ipv6_prefix", deserialize_with = "prefix::<_, 128>")]
+	ipv6_prefix: u8,
+	#[serde(default)]
+	pub(crate) action: RateLimitAction
+}
+
+fn default_ipv4_prefix() -> u8 {
+	32
+}
+
+fn default_ipv6_prefix() -> u8 {
+	64
+}
+
+/// Deserialize a number of queries, which has to be greater than 0.
+fn positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+	let value = f64::deserialize(deserializer)?;
+	if !(value > 0.0 && value.is_finite()) {
+		return Err(D::Error::custom(format!(
+			"queries_per_second must be greater than 0, got {value}"
+		)));
+	}
+	Ok(value)
+}
+
+/// Deserialize a burst, which has to allow at least a single query.
+fn burst<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+	let value = f64::deserialize(deserializer)?;
+	if !(value >= 1.0 && value.is_finite()) {
+		return Err(D::Error::custom(format!(
+			"burst must be at least 1, got {value}"
+		)));
+	}
+	Ok(Some(value))
+}
+
+/// Deserialize a prefix length, which is not longer than `MAX`.
+fn prefix<'de, D: Deserializer<'de>, const MAX: u8>(
+	deserializer: D
+) -> Result<u8, D::Error> {
+	let value = u8::deserialize(deserializer)?;
+	if value > MAX {
+		return Err(D::Error::custom(format!(
+			"prefix must be at most {MAX}, got {value}"
+		)));
+	}
+	Ok(value)
+}
+
+impl RateLimitConfig {
+	fn burst(&self) -> f64 {
+		// less than a single token would refuse every query
+		self.burst.unwrap_or(self.queries_per_second.max(1.0))
+	}
+}
+
+#[derive(Debug)]
+struct Bucket {
+	tokens: f64,
+	last_update: Instant
+}
+
+#[derive(Debug)]
+struct Buckets {
+	buckets: HashMap<IpNet, Bucket>,
+	last_cleanup: Instant
+}
+
+/// Token bucket rate limiter with one bucket per client prefix.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+	pub(crate) config: RateLimitConfig,
+	buckets: Mutex<Buckets>
+}
+
+impl RateLimiter {
+	pub(crate) fn new(config: RateLimitConfig) -> Self {
+		Self {
+			config,
+			buckets: Mutex::new(Buckets {
+				buckets: HashMap::new(),
+				last_cleanup: Instant::now()
+			})
+		}
+	}
+
+	/// Take a token of the bucket of the client `ip`.
+	/// Return false if the client is over its limit.
+	pub(crate) fn check(&self, ip: IpAddr) -> bool {
+		self.check_at(ip, Instant::now())
+	}
+
+	fn check_at(&self, ip: IpAddr, now: Instant) -> bool {
+		let ip = ip.to_canonical();
+		let prefix = match ip {
+			IpAddr::V4(_) => self.config.ipv4_prefix,
+			IpAddr::V6(_) => self.config.ipv6_prefix
+		};
+		let net = IpNet::new(ip, prefix)
+			.map(|net| net.trunc())
+			.unwrap_or_else(|_| IpNet::from(ip));
+		let burst = self.config.burst();
+		let refill = |bucket: &Bucket| {
+			let elapsed = now.saturating_duration_since(bucket.last_update);
+			(bucket.tokens + elapsed.as_secs_f64() * self.config.queries_per_second)
+				.min(burst)
+		};
+
+		let mut guard = self.buckets.lock().unwrap();
+		if now.saturating_duration_since(guard.last_cleanup) > CLEANUP_INTERVAL {
+			// full buckets behave like new buckets
+			guard.buckets.retain(|_, bucket| refill(bucket) < burst);
+			guard.last_cleanup = now;
+		}
+		let bucket = guard.buckets.entry(net).or_insert(Bucket {
+			tokens: burst,
+			last_update: now
+		});
+		bucket.tokens = refill(bucket);
+		bucket.last_update = now;
+		if bucket.tokens < 1.0 {
+			return false;
+		}
+		bucket.tokens -= 1.0;
+		true
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn limiter(burst: f64, ipv4_prefix: u8) -> RateLimiter {
+		RateLimiter::new(RateLimitConfig {
+			queries_per_second: 2.0,
+			burst: Some(burst),
+			ipv4_prefix,
+			ipv6_prefix: 56,
+			action: RateLimitAction::Refuse
+		})
+	}
+
+	#[test]
+	fn bucket() {
+		let limiter = limiter(3.0, 32);
+		let now = Instant::now();
+		for _ in 0 .. 3 {
+			assert!(limiter.check_at("192.168.1.2".parse().unwrap(), now));
+		}
+		assert!(!limiter.check_at("192.168.1.2".parse().unwrap(), now));
+		assert!(limiter.check_at("192.168.1.3".parse().unwrap(), now));
+		let now = now + Duration::from_millis(500);
+		assert!(limiter.check_at("192.168.1.2".parse().unwrap(), now));
+		assert!(!limiter.check_at("192.168.1.2".parse().unwrap(), now));
+	}
+
+	#[test]
+	fn prefix() {
+		let limiter = limiter(1.0, 24);
+		let now = Instant::now();
+		assert!(limiter.check_at("192.168.1.2".parse().unwrap(), now));
+		assert!(!limiter.check_at("192.168.1.3".parse().unwrap(), now));
+		assert!(!limiter.check_at("::ffff:192.168.1.4".parse().unwrap(), now));
+		assert!(limiter.check_at("fd00:0:0:1::1".parse().unwrap(), now));
+		assert!(!limiter.check_at("fd00:0:0:2::1".parse().unwrap(), now));
+		assert!(limiter.check_at("fd00:0:0:100::1".parse().unwrap(), now));
+	}
+
+	#[test]
+	fn config() {
+		let parse = |config: &str| toml::from_str::<RateLimitConfig>(config);
+		let config = parse("queries_per_second = 0.5").unwrap();
+		assert_eq!(config.burst(), 1.0);
+		assert_eq!(parse("queries_per_second = 5").unwrap().burst(), 5.0);
+		assert!(parse("queries_per_second = 0").is_err());
+		assert!(parse("queries_per_second = -1").is_err());
+		assert!(parse("queries_per_second = 1\nburst = 0.5").is_err());
+		assert!(parse("queries_per_second = 1\nipv4_prefix = 33").is_err());
+		assert!(parse("queries_per_second = 1\nipv6_prefix = 129").is_err());
+		assert!(parse("queries_per_second = 1\nipv6_prefix = 128").is_ok());
+		let limiter = RateLimiter::new(config);
+		assert!(limiter.check("192.168.1.2".parse().unwrap()));
+	}
+
+	#[test]
+	fn cleanup() {
+		let limiter = limiter(1.0, 32);
+		let now = Instant::now();
+		assert!(limiter.check_at("192.168.1.2".parse().unwrap(), now));
+		let now = now + CLEANUP_INTERVAL * 2;
+		assert!(limiter.check_at("192.168.1.3".parse().unwrap(), now));
+		assert_eq!(limiter.buckets.lock().unwrap().buckets.len(), 1);
+	}
+}
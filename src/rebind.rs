@@ -0,0 +1,99 @@
+use hickory_proto::rr::{LowerName, Name};
+use serde::Deserialize;
+use std::net::{IpAddr, Ipv4Addr};
+
+/// The `[rebind_protection]` config section.
+/// Forwarded answers must not contain private addresses, unless their name is allowed.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RebindConfig {
+	/// domains (including their subdomains), which are allowed to resolve to private addresses
+	#[serde(default)]
+	allowed_domains: Vec<Name>
+}
+
+impl RebindConfig {
+	/// Return true if `name` is allowed to resolve to private addresses.
+	pub(crate) fn allowed(&self, name: &LowerName) -> bool {
+		self.allowed_domains
+			.iter()
+			.any(|domain| LowerName::new(domain).zone_of(name))
+	}
+}
+
+/// Return true if `ip` is a private, loopback, link-local or unspecified address,
+/// which should not be returned for public domains.
+pub(crate) fn is_private(ip: IpAddr) -> bool {
+	match ip.to_canonical() {
+		IpAddr::V4(ip) => {
+			ip.is_private()
+				|| ip.is_loopback()
+				|| ip.is_link_local()
+				|| ip.is_unspecified()
+				// "this network", RFC 1122
+				|| ip.octets()[0] == 0
+				// shared address space, RFC 6598
+				|| (ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64)
+		},
+		IpAddr::V6(ip) => {
+			ip.is_loopback()
+				|| ip.is_unspecified()
+				|| ip.is_unique_local()
+				|| ip.is_unicast_link_local()
+				// NAT64 addresses of private ipv4 addresses, RFC 6052
+				|| (ip.segments()[.. 6] == [0x64, 0xff9b, 0, 0, 0, 0] && {
+					let [.., a, b, c, d] = ip.octets();
+					is_private(IpAddr::V4(Ipv4Addr::new(a, b, c, d)))
+				})
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::str::FromStr;
+
+	#[test]
+	fn private() {
+		for private in [
+			"10.1.2.3",
+			"172.16.0.1",
+			"192.168.1.1",
+			"127.0.0.1",
+			"169.254.1.1",
+			"100.64.0.1",
+			"0.0.0.0",
+			"::1",
+			"::",
+			"fd00::1",
+			"fe80::1",
+			"::ffff:192.168.1.1",
+			"64:ff9b::10.0.0.1"
+		] {
+			assert!(is_private(private.parse().unwrap()), "{private}");
+		}
+		for public in [
+			"1.1.1.1",
+			"172.32.0.1",
+			"100.128.0.1",
+			"2606:4700::1111",
+			"::ffff:1.1.1.1",
+			"64:ff9b::1.1.1.1"
+		] {
+			assert!(!is_private(public.parse().unwrap()), "{public}");
+		}
+	}
+
+	#[test]
+	fn allowed() {
+		let config: RebindConfig =
+			toml::from_str(r#"allowed_domains = ["lan", "fritz.box"]"#).unwrap();
+		let name = |name: &str| LowerName::from_str(name).unwrap();
+		assert!(config.allowed(&name("lan.")));
+		assert!(config.allowed(&name("nas.LAN.")));
+		assert!(config.allowed(&name("fritz.box.")));
+		assert!(!config.allowed(&name("box.")));
+		assert!(!config.allowed(&name("example.com.")));
+	}
+}
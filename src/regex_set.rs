@@ -0,0 +1,70 @@
+use bit_vec::BitVec;
+use regex::RegexSet;
+
+/// Regular expressions of all regex lists, compiled into a single [`RegexSet`],
+/// so a domain is matched against all of them at once.
+#[derive(Debug, Default)]
+pub(crate) struct DomainRegexSet {
+	set: RegexSet,
+	/// index of the list inside `BlockList.list_info` of each expression of `set`
+	sources: Vec<usize>
+}
+
+impl DomainRegexSet {
+	/// Compile the expressions of `entries`, each together with the index of its list.
+	pub(crate) fn new(entries: &[(String, usize)]) -> Result<Self, regex::Error> {
+		Ok(Self {
+			set: RegexSet::new(entries.iter().map(|(pattern, _)| pattern))?,
+			sources: entries.iter().map(|(_, source)| *source).collect()
+		})
+	}
+
+	/// Return the expressions and the indices of their lists, which match `domain`.
+	/// Only the lists selected by `filter` are used, `None` selects all lists.
+	pub(crate) fn matches<'a>(
+		&'a self,
+		domain: &str,
+		filter: Option<&'a BitVec>
+	) -> impl Iterator<Item = (&'a str, usize)> + 'a {
+		self.set
+			.matches(domain)
+			.into_iter()
+			.map(|i| (self.set.patterns()[i].as_str(), self.sources[i]))
+			.filter(move |(_, source)| {
+				filter.is_none_or(|filter| filter.get(*source) == Some(true))
+			})
+	}
+
+	/// Return true if one of the selected expressions matches `domain`.
+	pub(crate) fn is_match(&self, domain: &str, filter: Option<&BitVec>) -> bool {
+		self.matches(domain, filter).next().is_some()
+	}
+
+	pub(crate) fn len(&self) -> usize {
+		self.set.len()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn matches() {
+		let set = DomainRegexSet::new(&[
+			(r"^ad[s]?[0-9]*\.".to_owned(), 0),
+			(r"(^|\.)track(ing)?\.".to_owned(), 1)
+		])
+		.unwrap();
+		assert_eq!(
+			set.matches("ads2.example.com", None).collect::<Vec<_>>(),
+			vec![(r"^ad[s]?[0-9]*\.", 0)]
+		);
+		assert!(set.is_match("www.tracking.example.com", None));
+		assert!(!set.is_match("ready.example.com", None));
+
+		let filter = BitVec::from_elem(1, true);
+		assert!(set.is_match("ad.example.com", Some(&filter)));
+		assert!(!set.is_match("track.example.com", Some(&filter)));
+	}
+}
@@ -0,0 +1,133 @@
+use log::info;
+use std::{
+	future::pending,
+	path::{Path, PathBuf},
+	time::SystemTime
+};
+use tokio::{fs::metadata, time::interval};
+
+/// interval, in which the config file is checked for changes
+const WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Trigger of config reloads, by SIGHUP or by changes of the config file.
+pub(crate) struct ReloadTrigger {
+	#[cfg(unix)]
+	hangup: tokio::signal::unix::Signal,
+	/// path of the config file
+	path: PathBuf,
+	/// watch the config file for changes
+	watch: bool,
+	/// interval, in which the config file is checked for changes
+	watch_interval: std::time::Duration,
+	/// last modification time of the config file
+	modified: Option<SystemTime>
+}
+
+/// Return the last modification time of the file `path`.
+async fn modified(path: &Path) -> Option<SystemTime> {
+	metadata(path).await.ok()?.modified().ok()
+}
+
+impl ReloadTrigger {
+	pub(crate) async fn new(path: PathBuf, watch: bool) -> Self {
+		Self {
+			#[cfg(unix)]
+			hangup:
+				tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+					.expect("failed to listen for SIGHUP"),
+			modified: modified(&path).await,
+			path,
+			watch,
+			watch_interval: WATCH_INTERVAL
+		}
+	}
+
+	/// Enable or disable watching the config file.
+	pub(crate) async fn set_watch(&mut self, watch: bool) {
+		self.watch = watch;
+		self.modified = modified(&self.path).await;
+	}
+
+	/// Wait until the config should be reloaded.
+	pub(crate) async fn wait(&mut self) {
+		let watch = self.watch;
+		let path = &self.path;
+		let watch_interval = self.watch_interval;
+		let last_modified = &mut self.modified;
+		let changed = async move {
+			if !watch {
+				return pending().await;
+			}
+			let mut interval = interval(watch_interval);
+			loop {
+				interval.tick().await;
+				let modified = modified(path).await;
+				if modified != *last_modified {
+					*last_modified = modified;
+					return;
+				}
+			}
+		};
+		#[cfg(unix)]
+		let hangup = self.hangup.recv();
+		#[cfg(not(unix))]
+		let hangup = pending::<Option<()>>();
+		tokio::select! {
+			_ = hangup => info!("received SIGHUP, reload config"),
+			_ = changed => info!("config file was changed, reload config")
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::{fs, time::Duration};
+	use tokio::time::timeout;
+
+	// a single test, because SIGHUP wakes every trigger of the process
+	#[tokio::test]
+	async fn trigger() {
+		let path = std::env::temp_dir()
+			.join(format!("crab-hole-reload-{}.toml", std::process::id()));
+		fs::write(&path, "").unwrap();
+		let touch = |secs| {
+			fs::File::options()
+				.write(true)
+				.open(&path)
+				.unwrap()
+				.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+				.unwrap();
+		};
+		let mut trigger = ReloadTrigger::new(path.clone(), false).await;
+		trigger.watch_interval = Duration::from_millis(10);
+		touch(1);
+		assert!(timeout(Duration::from_millis(100), trigger.wait())
+			.await
+			.is_err());
+		trigger.set_watch(true).await;
+		assert!(timeout(Duration::from_millis(100), trigger.wait())
+			.await
+			.is_err());
+		touch(2);
+		assert!(timeout(Duration::from_secs(1), trigger.wait())
+			.await
+			.is_ok());
+		// each change triggers a single reload
+		assert!(timeout(Duration::from_millis(100), trigger.wait())
+			.await
+			.is_err());
+		#[cfg(unix)]
+		{
+			assert!(std::process::Command::new("kill")
+				.args(["-HUP", &std::process::id().to_string()])
+				.status()
+				.unwrap()
+				.success());
+			assert!(timeout(Duration::from_secs(1), trigger.wait())
+				.await
+				.is_ok());
+		}
+		fs::remove_file(&path).unwrap();
+	}
+}
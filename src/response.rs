@@ -0,0 +1,496 @@
+use crate::CARGO_PKG_NAME;
+use async_trait::async_trait;
+use hickory_proto::{
+	op::{Edns, Header, LowerQuery, Message, ResponseCode},
+	rr::{
+		rdata::{
+			opt::{EdnsCode, EdnsOption},
+			A, AAAA, SOA
+		},
+		Name, RData, Record, RecordType
+	},
+	serialize::binary::BinEncoder
+};
+use hickory_server::{
+	authority::{MessageResponse, MessageResponseBuilder},
+	server::{Request, ResponseHandler, ResponseInfo}
+};
+use serde::Deserialize;
+use std::{
+	io, iter,
+	net::{IpAddr, Ipv4Addr, Ipv6Addr},
+	sync::{Arc, Mutex}
+};
+
+/// EDNS option code of Extended DNS Errors, see RFC 8914
+const EDE_OPTION_CODE: u16 = 15;
+
+/// Info codes of Extended DNS Errors, see RFC 8914
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u16)]
+pub(crate) enum EdeCode {
+	/// the domain is blocked by a policy of this server
+	Blocked = 15,
+	/// records of the answer were removed by a policy of this server
+	Filtered = 17,
+	/// the server does not support a feature requested by the client
+	NotSupported = 21
+}
+
+impl EdeCode {
+	/// Create the EDNS option of this error with `extra_text` as human readable reason.
+	pub(crate) fn option(self, extra_text: &str) -> EdnsOption {
+		let data = (self as u16)
+			.to_be_bytes()
+			.into_iter()
+			.chain(extra_text.bytes())
+			.collect();
+		EdnsOption::Unknown(EDE_OPTION_CODE, data)
+	}
+}
+
+/// A response to a single query.
+#[derive(Debug)]
+pub(crate) struct Answer {
+	pub(crate) response_code: ResponseCode,
+	/// answer is authoritative for the queried name
+	pub(crate) authoritative: bool,
+	/// answer is incomplete, the client should retry over tcp
+	pub(crate) truncated: bool,
+	pub(crate) answers: Vec<Record>,
+	/// records of the authority section
+	pub(crate) name_servers: Vec<Record>,
+	pub(crate) additionals: Vec<Record>,
+	/// Extended DNS Errors of the response, each one is sent as its own EDNS option.
+	/// Other EDNS options of the upstream, like cookies or padding,
+	/// only belong to the upstream connection and are not forwarded.
+	pub(crate) options: Vec<EdnsOption>
+}
+
+impl Answer {
+	/// Create an answer without any records.
+	pub(crate) fn empty(response_code: ResponseCode) -> Self {
+		Self {
+			response_code,
+			authoritative: false,
+			truncated: false,
+			answers: Vec::new(),
+			name_servers: Vec::new(),
+			additionals: Vec::new(),
+			options: Vec::new()
+		}
+	}
+
+	/// Attach an Extended DNS Error to this answer.
+	pub(crate) fn with_ede(mut self, code: EdeCode, extra_text: &str) -> Self {
+		self.options.push(code.option(extra_text));
+		self
+	}
+
+	/// Merge the answers of multiple questions into a single answer.
+	/// Return `None` if there are no answers or if the answers have different
+	/// response codes, since a dns message can only hold a single response code.
+	pub(crate) fn merge(answers: impl IntoIterator<Item = Self>) -> Option<Self> {
+		let mut answers = answers.into_iter();
+		let mut merged = answers.next()?;
+		for answer in answers {
+			if answer.response_code != merged.response_code {
+				return None;
+			}
+			merged.authoritative &= answer.authoritative;
+			merged.truncated |= answer.truncated;
+			merged.answers.extend(answer.answers);
+			merged.name_servers.extend(answer.name_servers);
+			merged.additionals.extend(answer.additionals);
+			merged.options.extend(answer.options);
+		}
+		Some(merged)
+	}
+}
+
+impl From<Message> for Answer {
+	fn from(mut message: Message) -> Self {
+		Self {
+			response_code: message.response_code(),
+			authoritative: message.authoritative(),
+			truncated: message.truncated(),
+			answers: message.take_answers(),
+			name_servers: message.take_name_servers(),
+			additionals: message.take_additionals(),
+			options: message
+				.extensions_mut()
+				.take()
+				.map(|edns| {
+					edns.options()
+						.as_ref()
+						.iter()
+						.filter(|(code, _)| *code == EdnsCode::Unknown(EDE_OPTION_CODE))
+						.map(|(_, option)| option.clone())
+						.collect()
+				})
+				.unwrap_or_default()
+		}
+	}
+}
+
+/// Send `answer` to the client.
+/// The answer does only include an EDNS section, if the request has used EDNS,
+/// since a client without EDNS support does not expect an OPT record.
+pub(crate) async fn send<R: ResponseHandler>(
+	request: &Request,
+	response_handler: &mut R,
+	answer: &Answer
+) -> ResponseInfo {
+	let mut header = Header::response_from_request(request.header());
+	header.set_response_code(answer.response_code);
+	header.set_authoritative(answer.authoritative);
+	header.set_truncated(answer.truncated);
+	header.set_recursion_available(true);
+	let mut builder = MessageResponseBuilder::from_message_request(request);
+	if let Some(request_edns) = request.edns() {
+		let mut edns = Edns::new();
+		edns.set_max_payload(request_edns.max_payload().max(512));
+		edns.set_version(0);
+		edns.set_dnssec_ok(request_edns.flags().dnssec_ok);
+		// `insert` appends the option, so every Extended DNS Error is kept
+		for option in &answer.options {
+			edns.options_mut().insert(option.clone());
+		}
+		builder.edns(edns);
+	}
+	response_handler
+		.send_response(builder.build(
+			header,
+			answer.answers.iter(),
+			answer.name_servers.iter(),
+			iter::empty(),
+			answer.additionals.iter()
+		))
+		.await
+		.unwrap_or_else(|_| {
+			let mut header = Header::new();
+			header.set_response_code(ResponseCode::ServFail);
+			header.into()
+		})
+}
+
+/// [`ResponseHandler`], which keeps the response instead of sending it to the client.
+#[derive(Clone, Default)]
+pub(crate) struct Capture(Arc<Mutex<Option<Message>>>);
+
+impl Capture {
+	/// Return the captured response.
+	pub(crate) fn take(&self) -> Option<Message> {
+		self.0.lock().unwrap().take()
+	}
+}
+
+#[async_trait]
+impl ResponseHandler for Capture {
+	async fn send_response<'a>(
+		&mut self,
+		response: MessageResponse<
+			'_,
+			'a,
+			impl Iterator<Item = &'a Record> + Send + 'a,
+			impl Iterator<Item = &'a Record> + Send + 'a,
+			impl Iterator<Item = &'a Record> + Send + 'a,
+			impl Iterator<Item = &'a Record> + Send + 'a
+		>
+	) -> io::Result<ResponseInfo> {
+		let mut buffer = Vec::new();
+		let info = response.destructive_emit(&mut BinEncoder::new(&mut buffer))?;
+		let message = Message::from_vec(&buffer)?;
+		*self.0.lock().unwrap() = Some(message);
+		Ok(info)
+	}
+}
+
+fn default_ttl() -> u32 {
+	60
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum BlockMode {
+	/// answer with NXDOMAIN
+	#[default]
+	NxDomain,
+	/// answer with NOERROR, but without any records
+	NoData,
+	/// refuse to answer the query
+	Refused,
+	/// answer A queries with `0.0.0.0` and AAAA queries with `::`
+	Null,
+	/// answer A and AAAA queries with the configured `ipv4` and `ipv6` address.
+	/// If no address is configured for the record type, answer with NODATA.
+	Sinkhole
+}
+
+/// How blocked queries are answered.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct BlockResponse {
+	#[serde(default)]
+	pub(crate) mode: BlockMode,
+	pub(crate) ipv4: Option<Ipv4Addr>,
+	pub(crate) ipv6: Option<Ipv6Addr>,
+	/// ttl of synthesized records
+	#[serde(default = "default_ttl")]
+	pub(crate) ttl: u32,
+	/// ttl and MINIMUM of the synthesized SOA record,
+	/// which allows downstream caches to cache empty answers.
+	#[serde(default = "default_ttl")]
+	pub(crate) negative_ttl: u32
+}
+
+impl Default for BlockResponse {
+	fn default() -> Self {
+		Self {
+			mode: Default::default(),
+			ipv4: None,
+			ipv6: None,
+			ttl: default_ttl(),
+			negative_ttl: default_ttl()
+		}
+	}
+}
+
+impl BlockResponse {
+	/// Return the answer for a blocked query.
+	pub(crate) fn answer(&self, query: &LowerQuery) -> Answer {
+		let name = Name::from(query.name().clone());
+		let (ipv4, ipv6) = match self.mode {
+			BlockMode::NxDomain => return self.negative(name, ResponseCode::NXDomain),
+			BlockMode::NoData => return self.negative(name, ResponseCode::NoError),
+			BlockMode::Refused => return Answer::empty(ResponseCode::Refused),
+			BlockMode::Null => (Some(Ipv4Addr::UNSPECIFIED), Some(Ipv6Addr::UNSPECIFIED)),
+			BlockMode::Sinkhole => (self.ipv4, self.ipv6)
+		};
+		let rdata = match query.query_type() {
+			RecordType::A => ipv4.map(|ip| RData::A(A(ip))),
+			RecordType::AAAA => ipv6.map(|ip| RData::AAAA(AAAA(ip))),
+			_ => None
+		};
+		match rdata {
+			Some(rdata) => {
+				let mut answer = Answer::empty(ResponseCode::NoError);
+				answer
+					.answers
+					.push(Record::from_rdata(name, self.ttl, rdata));
+				answer
+			},
+			None => self.negative(name, ResponseCode::NoError)
+		}
+	}
+
+	/// Return the answer for a query of a domain from a hosts file,
+	/// which has the addresses `ips`.
+	pub(crate) fn hosts_answer(&self, query: &LowerQuery, ips: &[IpAddr]) -> Answer {
+		let name = Name::from(query.name().clone());
+		let mut answer = Answer::empty(ResponseCode::NoError);
+		answer.authoritative = true;
+		answer.answers = ips
+			.iter()
+			.filter_map(|ip| match (query.query_type(), ip) {
+				(RecordType::A, IpAddr::V4(ip)) => Some(RData::A(A(*ip))),
+				(RecordType::AAAA, IpAddr::V6(ip)) => Some(RData::AAAA(AAAA(*ip))),
+				_ => None
+			})
+			.map(|rdata| Record::from_rdata(name.clone(), self.ttl, rdata))
+			.collect();
+		if answer.answers.is_empty() {
+			answer.name_servers = self.negative(name, ResponseCode::NoError).name_servers;
+		}
+		answer
+	}
+
+	/// Create an answer without records,
+	/// but with a SOA record of `name` inside the authority section,
+	/// so the answer can be cached (RFC 2308).
+	pub(crate) fn negative(&self, name: Name, response_code: ResponseCode) -> Answer {
+		let mut answer = Answer::empty(response_code);
+		answer.name_servers.push(soa(name, self.negative_ttl));
+		answer
+	}
+}
+
+/// Create a SOA record for `name`, which is served by this server.
+/// `minimum` is used as ttl and as MINIMUM of the record.
+pub(crate) fn soa(name: Name, minimum: u32) -> Record {
+	let soa = SOA::new(
+		Name::from_ascii(format!("{CARGO_PKG_NAME}.")).unwrap(),
+		Name::from_ascii(format!("hostmaster.{CARGO_PKG_NAME}.")).unwrap(),
+		1,
+		1800,
+		900,
+		604800,
+		minimum
+	);
+	Record::from_rdata(name, minimum, RData::SOA(soa))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use hickory_proto::{op::Query, serialize::binary::BinDecodable, xfer::Protocol};
+	use hickory_server::authority::MessageRequest;
+	use std::str::FromStr;
+
+	fn query(name: &str, query_type: RecordType) -> LowerQuery {
+		LowerQuery::query(Query::query(Name::from_str(name).unwrap(), query_type))
+	}
+
+	#[test]
+	fn nxdomain() {
+		let response = BlockResponse {
+			negative_ttl: 300,
+			..Default::default()
+		};
+		let answer = response.answer(&query("example.com.", RecordType::A));
+		assert_eq!(answer.response_code, ResponseCode::NXDomain);
+		assert!(answer.answers.is_empty());
+		assert_eq!(answer.name_servers.len(), 1);
+		let soa = &answer.name_servers[0];
+		assert_eq!(soa.name(), &Name::from_str("example.com.").unwrap());
+		assert_eq!(soa.ttl(), 300);
+		let RData::SOA(soa) = soa.data() else {
+			panic!("unexpected record {soa:?}");
+		};
+		assert_eq!(soa.minimum(), 300);
+	}
+
+	#[test]
+	fn refused() {
+		let response = BlockResponse {
+			mode: BlockMode::Refused,
+			..Default::default()
+		};
+		let answer = response.answer(&query("example.com.", RecordType::A));
+		assert_eq!(answer.response_code, ResponseCode::Refused);
+		assert!(answer.answers.is_empty());
+		assert!(answer.name_servers.is_empty());
+	}
+
+	#[test]
+	fn null() {
+		let response = BlockResponse {
+			mode: BlockMode::Null,
+			ttl: 10,
+			..Default::default()
+		};
+		let answer = response.answer(&query("example.com.", RecordType::AAAA));
+		assert_eq!(answer.response_code, ResponseCode::NoError);
+		assert_eq!(answer.answers.len(), 1);
+		assert_eq!(answer.answers[0].ttl(), 10);
+		assert_eq!(
+			answer.answers[0].data(),
+			&RData::AAAA(AAAA(Ipv6Addr::UNSPECIFIED))
+		);
+		assert!(answer.name_servers.is_empty());
+		let answer = response.answer(&query("example.com.", RecordType::TXT));
+		assert_eq!(answer.response_code, ResponseCode::NoError);
+		assert!(answer.answers.is_empty());
+		assert_eq!(answer.name_servers.len(), 1);
+	}
+
+	#[test]
+	fn sinkhole() {
+		let response = BlockResponse {
+			mode: BlockMode::Sinkhole,
+			ipv4: Some(Ipv4Addr::new(192, 168, 0, 2)),
+			..Default::default()
+		};
+		let answer = response.answer(&query("example.com.", RecordType::A));
+		assert_eq!(
+			answer.answers[0].data(),
+			&RData::A(A(Ipv4Addr::new(192, 168, 0, 2)))
+		);
+		let answer = response.answer(&query("example.com.", RecordType::AAAA));
+		assert_eq!(answer.response_code, ResponseCode::NoError);
+		assert!(answer.answers.is_empty());
+	}
+
+	#[test]
+	fn hosts() {
+		let response = BlockResponse::default();
+		let ips = ["192.168.1.10".parse().unwrap(), "fd00::10".parse().unwrap()];
+		let answer = response.hosts_answer(&query("nas.home.", RecordType::A), &ips);
+		assert_eq!(answer.response_code, ResponseCode::NoError);
+		assert!(answer.authoritative);
+		assert_eq!(
+			answer.answers.iter().map(Record::data).collect::<Vec<_>>(),
+			vec![&RData::A(A(Ipv4Addr::new(192, 168, 1, 10)))]
+		);
+		let answer =
+			response.hosts_answer(&query("nas.home.", RecordType::AAAA), &ips[.. 1]);
+		assert_eq!(answer.response_code, ResponseCode::NoError);
+		assert!(answer.answers.is_empty());
+		assert_eq!(answer.name_servers.len(), 1);
+	}
+
+	#[test]
+	fn merge() {
+		let response = BlockResponse {
+			mode: BlockMode::Null,
+			..Default::default()
+		};
+		let answer = Answer::merge([
+			response.answer(&query("example.com.", RecordType::A)),
+			response.answer(&query("example.com.", RecordType::AAAA))
+		])
+		.unwrap();
+		assert_eq!(answer.response_code, ResponseCode::NoError);
+		assert_eq!(answer.answers.len(), 2);
+		assert!(Answer::merge([
+			response.answer(&query("example.com.", RecordType::A)),
+			Answer::empty(ResponseCode::NXDomain)
+		])
+		.is_none());
+		assert!(Answer::merge([]).is_none());
+	}
+
+	#[test]
+	fn ede_option() {
+		let option = EdeCode::Blocked.option("example");
+		assert_eq!(EdnsCode::from(&option), EdnsCode::Unknown(EDE_OPTION_CODE));
+		let EdnsOption::Unknown(_, data) = option else {
+			panic!("unexpected option {option:?}");
+		};
+		assert_eq!(&data[.. 2], &[0, 15]);
+		assert_eq!(&data[2 ..], b"example");
+	}
+
+	#[tokio::test]
+	async fn upstream_ede() {
+		let mut upstream = Message::new();
+		let mut edns = Edns::new();
+		edns.options_mut()
+			.insert(EdnsOption::Unknown(10, vec![1; 8]));
+		edns.options_mut()
+			.insert(EdeCode::Filtered.option("upstream"));
+		upstream.set_edns(edns);
+		let answer = Answer::from(upstream).with_ede(EdeCode::Blocked, "example");
+		assert_eq!(answer.options.len(), 2);
+
+		let mut message = Message::new();
+		message
+			.add_query(Query::query(
+				Name::from_str("example.com.").unwrap(),
+				RecordType::A
+			))
+			.set_edns(Edns::new());
+		let request = MessageRequest::from_bytes(&message.to_vec().unwrap()).unwrap();
+		let request =
+			Request::new(request, "127.0.0.1:53".parse().unwrap(), Protocol::Udp);
+		let mut capture = Capture::default();
+		send(&request, &mut capture, &answer).await;
+		let response = capture.take().unwrap();
+		let options = response.extensions().as_ref().unwrap().options();
+		assert_eq!(options.as_ref().len(), 2);
+		assert_eq!(options.get_all(EdnsCode::Unknown(EDE_OPTION_CODE)), vec![
+			&EdeCode::Filtered.option("upstream"),
+			&EdeCode::Blocked.option("example")
+		]);
+	}
+}
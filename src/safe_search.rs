@@ -0,0 +1,178 @@
+use hickory_proto::rr::{LowerName, Name};
+use log::warn;
+use serde::Deserialize;
+use std::{collections::HashMap, str::FromStr};
+
+const GOOGLE: &str = "forcesafesearch.google.com.";
+const BING: &str = "strict.bing.com.";
+const DUCKDUCKGO: &str = "safe.duckduckgo.com.";
+const YOUTUBE: &str = "restrict.youtube.com.";
+
+/// built-in rewrites, which force search engines into their safe search mode
+const BUILTIN: &[(&str, &str)] = &[
+	("google.com.", GOOGLE),
+	("www.google.com.", GOOGLE),
+	("bing.com.", BING),
+	("www.bing.com.", BING),
+	("duckduckgo.com.", DUCKDUCKGO),
+	("www.duckduckgo.com.", DUCKDUCKGO),
+	("start.duckduckgo.com.", DUCKDUCKGO),
+	("youtube.com.", YOUTUBE),
+	("www.youtube.com.", YOUTUBE),
+	("m.youtube.com.", YOUTUBE),
+	("youtubei.googleapis.com.", YOUTUBE),
+	("youtube.googleapis.com.", YOUTUBE),
+	("www.youtube-nocookie.com.", YOUTUBE)
+];
+
+fn default_builtin() -> bool {
+	true
+}
+
+fn default_ttl() -> u32 {
+	300
+}
+
+/// A user defined rewrite of the `[safe_search]` config section.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Rewrite {
+	domain: Name,
+	target: Name
+}
+
+/// The `[safe_search]` config section.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SafeSearchConfig {
+	/// use the built-in rewrites of Google, Bing, DuckDuckGo and YouTube
+	#[serde(default = "default_builtin")]
+	builtin: bool,
+	/// rewrites, which are used additionally to the built-in rewrites
+	#[serde(default)]
+	rewrites: Vec<Rewrite>,
+	/// names of the client groups, which use safe search. All clients are used if not set.
+	groups: Option<Vec<String>>,
+	/// ttl of the CNAME records
+	#[serde(default = "default_ttl")]
+	ttl: u32
+}
+
+/// Answers queries of search engines with a CNAME to their safe search domain.
+#[derive(Debug)]
+pub(crate) struct SafeSearch {
+	rewrites: HashMap<LowerName, Name>,
+	/// indices of the client groups, which use safe search
+	groups: Option<Vec<usize>>,
+	pub(crate) ttl: u32
+}
+
+impl SafeSearch {
+	/// Create the rewrite table of `config`.
+	/// `group_names` are the names of all configured client groups.
+	pub(crate) fn new(config: &SafeSearchConfig, group_names: &[&str]) -> Self {
+		let builtin = if config.builtin { BUILTIN } else { &[] };
+		let builtin = builtin.iter().map(|(domain, target)| {
+			(
+				Name::from_str(domain).unwrap(),
+				Name::from_str(target).unwrap()
+			)
+		});
+		let rewrites = builtin
+			.chain(
+				config
+					.rewrites
+					.iter()
+					.map(|rewrite| (rewrite.domain.clone(), rewrite.target.clone()))
+			)
+			.map(|(mut domain, mut target)| {
+				domain.set_fqdn(true);
+				target.set_fqdn(true);
+				(LowerName::new(&domain), target)
+			})
+			.collect();
+		let groups =
+			config.groups.as_ref().map(|groups| {
+				groups
+					.iter()
+					.filter_map(|name| {
+						let index = group_names.iter().position(|group| group == name);
+						if index.is_none() {
+							warn!("safe search group {name:?} is not configured at [[groups]]");
+						}
+						index
+					})
+					.collect()
+			});
+		Self {
+			rewrites,
+			groups,
+			ttl: config.ttl
+		}
+	}
+
+	/// Return the safe search domain of `name` for clients of the group `group`.
+	/// Return `None` if `name` is not rewritten.
+	pub(crate) fn rewrite(
+		&self,
+		name: &LowerName,
+		group: Option<usize>
+	) -> Option<&Name> {
+		if let Some(groups) = &self.groups {
+			if !group.is_some_and(|group| groups.contains(&group)) {
+				return None;
+			}
+		}
+		self.rewrites.get(name)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn load(config: &str) -> SafeSearch {
+		let config: SafeSearchConfig = toml::from_str(config).unwrap();
+		SafeSearch::new(&config, &["servers", "kids"])
+	}
+
+	fn name(name: &str) -> LowerName {
+		LowerName::from_str(name).unwrap()
+	}
+
+	#[test]
+	fn builtin() {
+		let safe_search = load(indoc::indoc! {r#"
+			[[rewrites]]
+			domain = "search.example.com"
+			target = "safe.example.com"
+		"#});
+		assert_eq!(
+			safe_search.rewrite(&name("www.Google.com."), None),
+			Some(&Name::from_str(GOOGLE).unwrap())
+		);
+		assert_eq!(
+			safe_search.rewrite(&name("search.example.com."), Some(0)),
+			Some(&Name::from_str("safe.example.com.").unwrap())
+		);
+		assert_eq!(safe_search.rewrite(&name("mail.google.com."), None), None);
+	}
+
+	#[test]
+	fn groups() {
+		let safe_search = load(indoc::indoc! {r#"
+			builtin = true
+			groups = ["kids"]
+		"#});
+		assert!(safe_search
+			.rewrite(&name("www.bing.com."), Some(1))
+			.is_some());
+		assert!(safe_search
+			.rewrite(&name("www.bing.com."), Some(0))
+			.is_none());
+		assert!(safe_search.rewrite(&name("www.bing.com."), None).is_none());
+
+		let safe_search = load("builtin = false");
+		assert!(safe_search.rewrite(&name("www.bing.com."), None).is_none());
+	}
+}
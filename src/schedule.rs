@@ -0,0 +1,196 @@
+use anyhow::{anyhow, bail, Context};
+use jiff::{civil::DateTime, Zoned};
+use serde::Deserialize;
+use std::{
+	sync::Mutex,
+	time::{Duration, Instant}
+};
+
+const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// local time used by the schedules and the instant until it is valid
+static NOW: Mutex<Option<(Instant, DateTime)>> = Mutex::new(None);
+
+/// Return the current local time.
+/// Schedules are precise to the minute, so the time is only looked up once per minute.
+pub(crate) fn now() -> DateTime {
+	let mut cached = NOW.lock().unwrap();
+	if let Some((until, now)) = *cached {
+		if Instant::now() < until {
+			return now;
+		}
+	}
+	let now = Zoned::now().datetime();
+	let until = Instant::now() + Duration::from_secs(60 - now.second() as u64)
+		- Duration::from_nanos(now.subsec_nanosecond() as u64);
+	*cached = Some((until, now));
+	now
+}
+
+/// A time range on some weekdays, like `sun-thu 20:00-07:00`.
+/// Ranges ending before their start end at the next day.
+/// Ranges without days are used at every day.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+struct TimeRange {
+	/// weekdays at which the range starts, starting with monday
+	days: [bool; 7],
+	/// minute of the day
+	start: u16,
+	/// minute of the day
+	end: u16
+}
+
+/// Parse a minute of the day like `07:30`.
+fn parse_time(time: &str) -> anyhow::Result<u16> {
+	let (hour, minute) = time
+		.split_once(':')
+		.with_context(|| format!("invalid time {time:?}, expected hh:mm"))?;
+	let hour: u16 = hour
+		.parse()
+		.with_context(|| format!("invalid hour of {time:?}"))?;
+	let minute: u16 = minute
+		.parse()
+		.with_context(|| format!("invalid minute of {time:?}"))?;
+	if hour > 24 || minute > 59 || (hour == 24 && minute != 0) {
+		bail!("invalid time {time:?}");
+	}
+	Ok(hour * 60 + minute)
+}
+
+/// Parse the index of a weekday like `mon`.
+fn parse_day(day: &str) -> anyhow::Result<usize> {
+	DAYS.iter()
+		.position(|name| name.eq_ignore_ascii_case(day))
+		.ok_or_else(|| {
+			anyhow!("invalid day {day:?}, expected one of {}", DAYS.join(", "))
+		})
+}
+
+impl TryFrom<String> for TimeRange {
+	type Error = anyhow::Error;
+
+	fn try_from(value: String) -> anyhow::Result<Self> {
+		let (days, times) = match value.trim().rsplit_once(char::is_whitespace) {
+			Some((days, times)) => (Some(days.trim()), times),
+			None => (None, value.trim())
+		};
+		let mut range = Self {
+			days: [days.is_none(); 7],
+			start: 0,
+			end: 0
+		};
+		for days in days.into_iter().flat_map(|days| days.split(',')) {
+			let (first, last) = days.split_once('-').unwrap_or((days, days));
+			let (first, last) = (parse_day(first.trim())?, parse_day(last.trim())?);
+			// ranges like `fri-mon` wrap around the end of the week
+			let mut day = first;
+			loop {
+				range.days[day] = true;
+				if day == last {
+					break;
+				}
+				day = (day + 1) % 7;
+			}
+		}
+		let (start, end) = times.split_once('-').with_context(|| {
+			format!("invalid time range {times:?}, expected hh:mm-hh:mm")
+		})?;
+		range.start = parse_time(start)?;
+		range.end = parse_time(end)?;
+		Ok(range)
+	}
+}
+
+impl TimeRange {
+	fn contains(&self, now: DateTime) -> bool {
+		let day = now.weekday().to_monday_zero_offset() as usize;
+		let yesterday = (day + 6) % 7;
+		let minute = now.hour() as u16 * 60 + now.minute() as u16;
+		if self.start < self.end {
+			self.days[day] && self.start <= minute && minute < self.end
+		} else {
+			// the range continues at the next day
+			(self.days[day] && minute >= self.start)
+				|| (self.days[yesterday] && minute < self.end % MINUTES_PER_DAY)
+		}
+	}
+}
+
+/// Times, at which a list or a group is active.
+/// It is active if one of its time ranges contains the current local time.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct Schedule(Vec<TimeRange>);
+
+impl Schedule {
+	/// Return true if the schedule is active at `now`.
+	pub(crate) fn active_at(&self, now: DateTime) -> bool {
+		self.0.iter().any(|range| range.contains(now))
+	}
+
+	/// Return true if the schedule is active at the current local time.
+	pub(crate) fn active(&self) -> bool {
+		self.active_at(now())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use jiff::civil::date;
+
+	fn schedule(ranges: &[&str]) -> Schedule {
+		Schedule(
+			ranges
+				.iter()
+				.map(|range| TimeRange::try_from(range.to_string()).unwrap())
+				.collect()
+		)
+	}
+
+	#[test]
+	fn parse() {
+		let range = TimeRange::try_from("fri-mon,wed 20:00-07:30".to_owned()).unwrap();
+		assert_eq!(range, TimeRange {
+			days: [true, false, true, false, true, true, true],
+			start: 20 * 60,
+			end: 7 * 60 + 30
+		});
+		let range = TimeRange::try_from("08:00-24:00".to_owned()).unwrap();
+		assert_eq!(range.days, [true; 7]);
+		assert!(TimeRange::try_from("monday 08:00-12:00".to_owned()).is_err());
+		assert!(TimeRange::try_from("mon 08:00".to_owned()).is_err());
+		assert!(TimeRange::try_from("mon 25:00-26:00".to_owned()).is_err());
+	}
+
+	#[test]
+	fn school_nights() {
+		let schedule = schedule(&["sun-thu 20:00-07:00"]);
+		// 2024-01-07 is a sunday
+		let at = |day, hour, minute| date(2024, 1, day).at(hour, minute, 0, 0);
+		assert!(!schedule.active_at(at(7, 19, 59)));
+		assert!(schedule.active_at(at(7, 20, 0)));
+		assert!(schedule.active_at(at(8, 6, 59)));
+		assert!(!schedule.active_at(at(8, 7, 0)));
+		assert!(schedule.active_at(at(11, 23, 0)));
+		// night from thursday to friday
+		assert!(schedule.active_at(at(12, 3, 0)));
+		assert!(!schedule.active_at(at(12, 21, 0)));
+		assert!(!schedule.active_at(at(13, 3, 0)));
+		// night from saturday to sunday
+		assert!(!schedule.active_at(at(14, 3, 0)));
+	}
+
+	#[test]
+	fn daytime() {
+		let schedule = schedule(&["sat,sun 10:00-12:00", "wed 00:00-24:00"]);
+		let at = |day, hour| date(2024, 1, day).at(hour, 0, 0, 0);
+		assert!(schedule.active_at(at(13, 11)));
+		assert!(!schedule.active_at(at(13, 12)));
+		assert!(schedule.active_at(at(10, 0)));
+		assert!(schedule.active_at(at(10, 23)));
+		assert!(!schedule.active_at(at(11, 0)));
+	}
+}
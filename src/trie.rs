@@ -11,10 +11,113 @@ pub(crate) struct TrieValue {
 	/// `true`s in [`BitVec`] are the indices of those lists in `BlockList.list_info`
 	/// that contain the domain.
 	pub(crate) block_source: BitVec,
-	/// domain was manuall allowed.
-	/// Allows have a higher piority than blocks
-	/// I think we do not need track the sources here, since they are regular not many allow lists.
-	pub(crate) allowed: bool
+	/// `true`s are the indices of the lists, which block the subdomains too
+	/// (`*.<domain>` or `||<domain>^` entries), independent of [`ListFilter::subdomains`].
+	/// Boxed to keep the entries without subdomains small.
+	pub(crate) subdomain_source: Option<Box<BitVec>>,
+	/// domain was manuall allowed, if `Some`.
+	/// Allows have a higher piority than blocks.
+	/// `true`s are the indices of the allow lists, which contain the domain.
+	/// Boxed to keep the entries, which are not allowed, small.
+	pub(crate) allow_source: Option<Box<BitVec>>,
+	/// `true`s are the indices of the allow lists, which allow the subdomains too.
+	pub(crate) allow_subdomain_source: Option<Box<BitVec>>
+}
+
+impl TrieValue {
+	/// Return true if the domain was allowed by one of the allow lists.
+	pub(crate) fn allowed(&self) -> bool {
+		self.allow_source.is_some()
+	}
+
+	/// Return true if the entry is relevant for the lists selected by `filter`.
+	fn selected(&self, filter: ListFilter<'_>) -> bool {
+		intersects(&self.block_source, filter.block) || self.allowed_by(filter, false)
+	}
+
+	/// Return true if the entry is relevant for subdomains of its domain
+	/// for the lists selected by `filter`.
+	fn subdomains_selected(&self, filter: ListFilter<'_>) -> bool {
+		self.subdomain_source
+			.as_ref()
+			.is_some_and(|subdomain_source| intersects(subdomain_source, filter.block))
+			|| self.block_source.iter().enumerate().any(|(i, is_in)| {
+				is_in
+					&& [filter.block, filter.subdomains]
+						.iter()
+						.all(|lists| lists.is_none_or(|lists| lists.get(i) == Some(true)))
+			}) || self.allowed_by(filter, true)
+	}
+
+	/// Return the indices of the lists selected by `filter`, which block the domain.
+	/// `parent` is true if the entry belongs to a parent domain of the blocked domain.
+	pub(crate) fn lists<'a>(
+		&'a self,
+		filter: ListFilter<'a>,
+		parent: bool
+	) -> impl Iterator<Item = usize> + 'a {
+		let selected = |lists: Option<&BitVec>, i: usize| {
+			lists.is_none_or(|lists| lists.get(i) == Some(true))
+		};
+		self.block_source
+			.iter()
+			.enumerate()
+			.filter(move |(i, is_in)| {
+				*is_in
+					&& selected(filter.block, *i)
+					&& (!parent
+						|| selected(filter.subdomains, *i)
+						|| self
+							.subdomain_source
+							.as_ref()
+							.is_some_and(|source| source.get(*i) == Some(true)))
+			})
+			.map(|(i, _)| i)
+	}
+
+	/// Return true if the entry is allowed by one of the allow lists selected by `filter`.
+	/// `parent` is true if the entry belongs to a parent domain of the checked domain,
+	/// which is only allowed by allow lists, that allow the subdomains too.
+	fn allowed_by(&self, filter: ListFilter<'_>, parent: bool) -> bool {
+		let source = if parent {
+			&self.allow_subdomain_source
+		} else {
+			&self.allow_source
+		};
+		source
+			.as_ref()
+			.is_some_and(|source| intersects(source, filter.allow))
+	}
+}
+
+/// Subset of the block and allow lists, which are used to check a domain.
+/// `None` selects all lists.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct ListFilter<'a> {
+	pub(crate) block: Option<&'a BitVec>,
+	pub(crate) allow: Option<&'a BitVec>,
+	/// block lists, whose entries block their subdomains too
+	pub(crate) subdomains: Option<&'a BitVec>
+}
+
+/// Return true if one of the lists of `source` is selected by `filter`.
+pub(crate) fn intersects(source: &BitVec, filter: Option<&BitVec>) -> bool {
+	match filter {
+		None => source.any(),
+		Some(filter) => source.iter().zip(filter).any(|(a, b)| a && b)
+	}
+}
+
+/// Mark the list `list_info_index` inside `source`.
+/// Return true if the list was already marked.
+pub(crate) fn set_source(source: &mut BitVec, list_info_index: usize) -> bool {
+	if list_info_index + 1 > source.len() {
+		let grow = list_info_index + 1 - source.len();
+		source.grow(grow, false);
+	}
+	let was_already_marked = source[list_info_index];
+	source.set(list_info_index, true);
+	was_already_marked
 }
 
 #[derive(Default)]
@@ -51,7 +154,13 @@ impl Trie {
 	/// will be marked as coming from the list as well and returns true. If the
 	/// domain is already in the trie and is marked as coming from the list, it will
 	/// return false.
-	pub(crate) fn insert(&mut self, domain: &str, list_info_index: usize) -> bool {
+	/// If `include_subdomains` is true, the list blocks all subdomains of the domain too.
+	pub(crate) fn insert(
+		&mut self,
+		domain: &str,
+		list_info_index: usize,
+		include_subdomains: bool
+	) -> bool {
 		let mut was_already_add_by_this_list = false;
 		if domain.is_empty() {
 			return was_already_add_by_this_list;
@@ -63,50 +172,94 @@ impl Trie {
 		index.set(list_info_index, true);
 		let old_value = self.0.insert(key.clone(), TrieValue {
 			block_source: index,
-			allowed: false
+			subdomain_source: None,
+			allow_source: None,
+			allow_subdomain_source: None
 		});
 		if let Some(mut old_value) = old_value {
 			// if value already exist, we need to add the entry to the existing bitvec
-			was_already_add_by_this_list = old_value
-				.block_source
-				.get(list_info_index)
-				.is_some_and(|f| f);
-			if list_info_index + 1 > old_value.block_source.len() {
-				let grow = list_info_index + 1 - old_value.block_source.len();
-				old_value.block_source.grow(grow, false);
-				old_value.block_source.set(list_info_index, true);
-			}
-			self.0.insert(key, old_value);
+			was_already_add_by_this_list =
+				set_source(&mut old_value.block_source, list_info_index);
+			self.0.insert(key.clone(), old_value);
 		};
+		if include_subdomains {
+			let value = self.0.get_mut(&key).unwrap();
+			set_source(
+				value.subdomain_source.get_or_insert_with(Default::default),
+				list_info_index
+			);
+		}
 		was_already_add_by_this_list
 	}
 
 	/// return true if domain is blocked
+	#[cfg(test)]
 	pub(crate) fn blocked(&self, domain: &str, include_subdomains: bool) -> bool {
-		if include_subdomains {
-			let mut key = Vec::new();
-			let mut domain = domain.bytes().rev();
-			let mut sub_trie = self.0.subtrie(&Vec::new());
-			let mut allowed = true;
-			while !sub_trie.is_empty() {
-				for byte in &mut domain {
-					if byte == b'.' {
-						break;
-					}
-					key.push(byte);
+		let exact = BitVec::new();
+		let filter = ListFilter {
+			subdomains: (!include_subdomains).then_some(&exact),
+			..Default::default()
+		};
+		self.blocked_by(domain, filter).is_some()
+	}
+
+	/// return the entry, which blocks the domain or `None` if the domain is not blocked.
+	/// The returned bool is true, if the entry belongs to a parent domain of the domain.
+	/// Only the lists selected by `filter` are used.
+	/// Parent domains block the domain, if their list is selected by `filter.subdomains`
+	/// or if they block their subdomains on their own.
+	pub(crate) fn blocked_by(
+		&self,
+		domain: &str,
+		filter: ListFilter<'_>
+	) -> Option<(&TrieValue, bool)> {
+		self.deepest(domain, filter)
+			.filter(|(f, parent)| !f.allowed_by(filter, *parent))
+	}
+
+	/// return true if the domain was allowed by one of the allow lists selected by `filter`.
+	/// Like at [`Self::blocked_by()`] parent domains, which are allowed with their subdomains,
+	/// allow their subdomains too, as long as no more specific entry blocks the domain.
+	pub(crate) fn allowed_by(&self, domain: &str, filter: ListFilter<'_>) -> bool {
+		self.deepest(domain, filter)
+			.is_some_and(|(f, parent)| f.allowed_by(filter, parent))
+	}
+
+	/// return the most specific entry of the domain or of one of its parent domains,
+	/// which is relevant for the domain and the lists selected by `filter`.
+	/// The returned bool is true, if the entry belongs to a parent domain of the domain.
+	fn deepest(
+		&self,
+		domain: &str,
+		filter: ListFilter<'_>
+	) -> Option<(&TrieValue, bool)> {
+		let domain_len = domain.len();
+		let mut key = Vec::new();
+		let mut domain = domain.bytes().rev();
+		let mut sub_trie = self.0.subtrie(&Vec::new());
+		let mut hit = None;
+		while !sub_trie.is_empty() {
+			for byte in &mut domain {
+				if byte == b'.' {
+					break;
 				}
-				sub_trie = sub_trie.subtrie(&*key);
-				let trie_value = sub_trie.get(&*key);
-				if let Some(trie_value) = trie_value {
-					allowed = trie_value.allowed;
+				key.push(byte);
+			}
+			sub_trie = sub_trie.subtrie(&*key);
+			let trie_value = sub_trie.get(&*key).filter(|f| {
+				if key.len() == domain_len {
+					f.selected(filter)
+				} else {
+					// parent domain of the queried domain
+					f.subdomains_selected(filter)
 				}
-				key.push(b'.');
+			});
+			if let Some(trie_value) = trie_value {
+				hit = Some((trie_value, key.len() != domain_len));
 			}
-			!allowed
-		} else {
-			let key: Vec<u8> = domain.bytes().rev().collect();
-			self.0.get(&key).is_some_and(|f| !f.allowed)
+			key.push(b'.');
 		}
+		hit
 	}
 
 	/// return all block and allow entrys assiated with `domain` including subdomains
@@ -128,24 +281,41 @@ impl Trie {
 		hits
 	}
 
-	/// allow a domain, even it was blocked before.
+	/// allow a domain by the allow list `list_info_index`, even it was blocked before.
 	/// After calling this function [`Self::insert()`] should no called anymore at the same trie.
-	pub(crate) fn allow(&mut self, domain: &str, remove_subdoamains: bool) {
+	pub(crate) fn allow(
+		&mut self,
+		domain: &str,
+		remove_subdoamains: bool,
+		list_info_index: usize
+	) {
 		let mut key: Vec<u8> = domain.bytes().rev().chain(iter::once(b'.')).collect();
 		if remove_subdoamains {
 			for (_, entry) in self.0.iter_prefix_mut(&key) {
-				entry.allowed = true;
+				set_source(
+					entry.allow_source.get_or_insert_with(Default::default),
+					list_info_index
+				);
 			}
 		}
 		key.pop();
-		if let Some(entry) = self.0.get_mut(&key) {
-			entry.allowed = true;
-		} else {
-			let entry = TrieValue {
-				allowed: true,
-				block_source: BitVec::new()
-			};
-			self.0.insert(key.clone(), entry);
+		let entry = self.0.entry(key).or_insert_with(|| TrieValue {
+			block_source: BitVec::new(),
+			subdomain_source: None,
+			allow_source: None,
+			allow_subdomain_source: None
+		});
+		set_source(
+			entry.allow_source.get_or_insert_with(Default::default),
+			list_info_index
+		);
+		if remove_subdoamains {
+			set_source(
+				entry
+					.allow_subdomain_source
+					.get_or_insert_with(Default::default),
+				list_info_index
+			);
 		}
 	}
 
@@ -164,12 +334,12 @@ mod tests {
 	fn simple() {
 		let mut tree = Trie::new();
 		assert!(!tree.blocked("example.com", false));
-		tree.insert("example.com", 0);
+		tree.insert("example.com", 0, false);
 		assert!(tree.blocked("example.com", false));
 		assert!(!tree.blocked("xample.com", false));
 		assert!(!tree.blocked("example.co", false));
 		assert!(!tree.blocked("eexample.com", false));
-		tree.insert("eexample.com", 0);
+		tree.insert("eexample.com", 0, false);
 		assert!(tree.blocked("eexample.com", false));
 	}
 
@@ -178,13 +348,13 @@ mod tests {
 		let mut tree = Trie::new();
 		dbg!(&tree);
 		assert!(!tree.blocked("example.com", true));
-		tree.insert("example.com", 0);
+		tree.insert("example.com", 0, false);
 		dbg!(&tree);
 		assert!(tree.blocked("example.com", true));
 		assert!(!tree.blocked("xample.com", true));
 		assert!(!tree.blocked("example.co", true));
 		assert!(!tree.blocked("eexample.com", true));
-		tree.insert("eexample.com", 0);
+		tree.insert("eexample.com", 0, false);
 		dbg!(&tree);
 		assert!(tree.blocked("eexample.com", true));
 		assert!(tree.blocked("foo.example.com", true));
@@ -194,12 +364,12 @@ mod tests {
 	#[test]
 	fn allow() {
 		let mut tree = Trie::new();
-		tree.insert("example.com", 0);
-		tree.insert("sub.example.com", 0);
+		tree.insert("example.com", 0, false);
+		tree.insert("sub.example.com", 0, false);
 		dbg!(&tree);
 		assert!(tree.blocked("example.com", false));
 		assert!(tree.blocked("sub.example.com", false));
-		tree.allow("example.com", false);
+		tree.allow("example.com", false, 0);
 		dbg!(&tree);
 		assert!(!tree.blocked("example.com", false));
 		assert!(tree.blocked("sub.example.com", false));
@@ -208,12 +378,12 @@ mod tests {
 	#[test]
 	fn allow_all_subdomains() {
 		let mut tree = Trie::new();
-		tree.insert("example.com", 0);
-		tree.insert("sub.example.com", 0);
+		tree.insert("example.com", 0, false);
+		tree.insert("sub.example.com", 0, false);
 		dbg!(&tree);
 		assert!(tree.blocked("example.com", false));
 		assert!(tree.blocked("sub.example.com", false));
-		tree.allow("example.com", true);
+		tree.allow("example.com", true, 0);
 		dbg!(&tree);
 		assert!(!tree.blocked("example.com", false));
 		assert!(!tree.blocked("sub.example.com", false));
@@ -222,17 +392,126 @@ mod tests {
 	#[test]
 	fn allow_sub_domain() {
 		let mut tree = Trie::new();
-		tree.insert("example.com", 0);
-		tree.insert("sub.example.com", 0);
+		tree.insert("example.com", 0, false);
+		tree.insert("sub.example.com", 0, false);
 		dbg!(&tree);
 		assert!(tree.blocked("example.com", true));
 		assert!(tree.blocked("sub.example.com", true));
-		tree.allow("sub.example.com", true);
+		tree.allow("sub.example.com", true, 0);
 		dbg!(&tree);
 		assert!(tree.blocked("example.com", true));
 		assert!(!tree.blocked("sub.example.com", true));
 	}
 
+	#[test]
+	fn filter() {
+		let mut tree = Trie::new();
+		tree.insert("example.com", 0, false);
+		tree.insert("sub.example.com", 1, false);
+		tree.allow("other.example.com", false, 0);
+		let first = BitVec::from_elem(1, true);
+		let second = BitVec::from_fn(2, |i| i == 1);
+		let exact = BitVec::new();
+		let filter = |block, allow, subdomains| ListFilter {
+			block,
+			allow,
+			subdomains
+		};
+		assert!(tree
+			.blocked_by("sub.example.com", filter(Some(&first), None, None))
+			.is_some());
+		assert!(tree
+			.blocked_by("sub.example.com", filter(Some(&first), None, Some(&exact)))
+			.is_none());
+		assert!(tree
+			.blocked_by("sub.example.com", filter(None, None, Some(&second)))
+			.is_some());
+		let (value, parent) = tree
+			.blocked_by("other.sub.example.com", filter(None, None, Some(&second)))
+			.unwrap();
+		assert!(parent);
+		assert_eq!(
+			value
+				.lists(filter(None, None, Some(&second)), parent)
+				.collect::<Vec<_>>(),
+			vec![1]
+		);
+		assert!(tree
+			.blocked_by("other.example.com", filter(None, None, Some(&second)))
+			.is_none());
+		assert!(tree
+			.blocked_by("example.com", filter(Some(&second), None, None))
+			.is_none());
+		assert!(tree
+			.blocked_by("other.example.com", filter(None, None, None))
+			.is_none());
+		assert!(tree
+			.blocked_by("other.example.com", filter(None, Some(&exact), None))
+			.is_some());
+	}
+
+	#[test]
+	fn wildcard() {
+		let mut tree = Trie::new();
+		tree.insert("example.com", 0, true);
+		tree.insert("example.org", 0, false);
+		tree.insert("example.org", 1, true);
+		assert!(tree.blocked("example.com", false));
+		assert!(tree.blocked("sub.example.com", false));
+		assert!(tree.blocked("sub.sub.example.com", false));
+		assert!(!tree.blocked("eexample.com", false));
+		assert!(tree.blocked("sub.example.org", false));
+		let first = BitVec::from_elem(1, true);
+		let exact = BitVec::new();
+		let filter = ListFilter {
+			block: Some(&first),
+			allow: None,
+			subdomains: Some(&exact)
+		};
+		assert!(tree.blocked_by("example.org", filter).is_some());
+		assert!(tree.blocked_by("sub.example.org", filter).is_none());
+		tree.allow("sub.example.com", false, 0);
+		assert!(!tree.blocked("sub.example.com", false));
+		assert!(tree.blocked("other.example.com", false));
+	}
+
+	#[test]
+	fn allowed_by() {
+		let mut tree = Trie::new();
+		tree.insert("ads.example.org", 0, false);
+		tree.allow("example.com", true, 0);
+		tree.allow("example.org", true, 0);
+		let filter = ListFilter::default();
+		assert!(tree.allowed_by("example.com", filter));
+		// subdomains, which are not part of any list
+		assert!(tree.allowed_by("ads.example.com", filter));
+		assert!(tree.allowed_by("sub.ads.example.com", filter));
+		assert!(tree.allowed_by("ads.example.org", filter));
+		assert!(!tree.allowed_by("eexample.com", filter));
+		assert!(!tree.allowed_by("com", filter));
+		let exact = BitVec::new();
+		let filter = ListFilter {
+			allow: Some(&exact),
+			..Default::default()
+		};
+		assert!(!tree.allowed_by("ads.example.com", filter));
+	}
+
+	#[test]
+	fn allow_without_subdomains() {
+		let mut tree = Trie::new();
+		tree.insert("example.com", 0, true);
+		tree.allow("example.com", false, 0);
+		tree.allow("example.org", false, 0);
+		let filter = ListFilter::default();
+		assert!(tree.allowed_by("example.com", filter));
+		assert!(!tree.blocked("example.com", true));
+		// a parent, which is allowed without its subdomains, does not allow them
+		assert!(!tree.allowed_by("sub.example.com", filter));
+		assert!(tree.blocked("sub.example.com", false));
+		assert!(!tree.allowed_by("sub.example.org", filter));
+	}
+
 	#[cfg(nightly)]
 	mod bench {
 		use super::*;
@@ -283,7 +562,7 @@ mod tests {
 			let mut trie = Trie::new();
 			b.iter(|| {
 				for domain in &domains {
-					trie.insert(domain, 0);
+					trie.insert(domain, 0, false);
 				}
 			});
 		}
@@ -293,7 +572,7 @@ mod tests {
 			let domains = load_domains("/bench/domains.txt");
 			let mut trie = Trie::new();
 			for domain in &domains {
-				trie.insert(domain, 0);
+				trie.insert(domain, 0, false);
 			}
 			let domains: HashSet<String> = domains.into_iter().take(1000).collect();
 			b.iter(|| {
@@ -310,7 +589,7 @@ mod tests {
 			let domains = load_domains("/bench/domains.txt");
 			let mut trie = Trie::new();
 			for domain in &domains {
-				trie.insert(domain, 0);
+				trie.insert(domain, 0, false);
 			}
 			drop(domains);
 			mem_print();
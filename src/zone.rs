@@ -0,0 +1,37 @@
+use crate::parser;
+use anyhow::{anyhow, Context};
+use hickory_proto::rr::Name;
+use hickory_server::{authority::ZoneType, store::in_memory::InMemoryAuthority};
+use serde::Deserialize;
+use std::{fs, path::PathBuf};
+
+/// An authoritative zone of the `[[zones]]` config section,
+/// which is loaded from a zone file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ZoneConfig {
+	name: Name,
+	file: PathBuf
+}
+
+impl ZoneConfig {
+	/// Load the zone file and create an authority, which serves its records.
+	pub(crate) fn load(&self) -> anyhow::Result<InMemoryAuthority> {
+		let mut origin = self.name.clone();
+		origin.set_fqdn(true);
+		let input = fs::read_to_string(&self.file)
+			.with_context(|| format!("Failed to read zone file {:?}", self.file))?;
+		let path_str = self.file.to_string_lossy();
+		let zone = parser::Zone::parse(&path_str, &self.file, &input, origin)
+			.map_err(|err| anyhow!("{}", err.msg()))?;
+		InMemoryAuthority::new(
+			zone.origin,
+			zone.records,
+			ZoneType::Primary,
+			false,
+			#[cfg(any(feature = "ring", feature = "aws-lc-rs"))]
+			None
+		)
+		.map_err(|err| anyhow!("Failed to load zone file {:?}: {err}", self.file))
+	}
+}